@@ -6,3 +6,51 @@ pub struct InputComment {
     pub post_id: u32,
     pub content: String,
 }
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputMute {
+    /// How long the user stays muted
+    pub minutes: u32,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputReport {
+    pub target: crate::ReportTarget,
+    pub target_id: u32,
+    pub reason: crate::ReportReason,
+    /// Anything else the reporter wants moderators to know
+    #[serde(default)]
+    pub details: Option<String>,
+}
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputWord {
+    pub word: String,
+    pub list: crate::WordList,
+}
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputReason {
+    pub reason: String,
+}
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputPasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Used only as an input to an API endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputDeleteAccount {
+    /// The account's password, so that an unattended session cannot delete it
+    pub password: String,
+    pub content: crate::DeletedContent,
+}
@@ -2,12 +2,114 @@ use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
 pub mod inputs;
+pub mod username;
 
-/// A user session that can be `Serialized` and `Deserialized`
+/// Usernames that nobody can register, or anything that looks like them,
+/// so that nobody can pretend to be the assistant or staff. See [`username::validate_username`].
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "ai",
+    "assistant",
+    "system",
+    "moderator",
+    "mod",
+    "admin",
+    "administrator",
+    "staff",
+];
+
+/// Who wrote a [`Post`] or [`Comment`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthorKind {
+    /// A registered user
+    #[default]
+    User,
+    /// The AI
+    Assistant,
+    /// The server itself, like support resources on posts flagged as at risk
+    System,
+    /// A user acting as a moderator
+    Moderator,
+}
+
+impl AuthorKind {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuthorKind::User => "user",
+            AuthorKind::Assistant => "assistant",
+            AuthorKind::System => "system",
+            AuthorKind::Moderator => "moderator",
+        }
+    }
+
+    /// Parse a name stored in the database. Unknown names are treated as [`AuthorKind::User`].
+    #[must_use]
+    pub fn from_db(kind: &str) -> Self {
+        match kind {
+            "assistant" => AuthorKind::Assistant,
+            "system" => AuthorKind::System,
+            "moderator" => AuthorKind::Moderator,
+            _ => AuthorKind::User,
+        }
+    }
+
+    /// The username shown for authors that are not a registered user
+    #[must_use]
+    pub fn display_name(self) -> Option<&'static str> {
+        match self {
+            AuthorKind::Assistant => Some("AI"),
+            AuthorKind::System => Some("System"),
+            AuthorKind::User | AuthorKind::Moderator => None,
+        }
+    }
+}
+
+/// What an account is allowed to do. Each role can do everything the roles before it can.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    /// Can handle reports and risk reviews, delete posts and comments, and ban users
+    Moderator,
+    /// Can also change other accounts' roles
+    Admin,
+}
+
+impl Role {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Parse a name stored in the database. Unknown names are treated as [`Role::User`].
+    #[must_use]
+    pub fn from_db(role: &str) -> Self {
+        match role {
+            "moderator" => Role::Moderator,
+            "admin" => Role::Admin,
+            _ => Role::User,
+        }
+    }
+}
+
+/// One of a user's logged-in devices, without its token
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Session {
-    pub username: String,
-    pub id: String,
+    pub id: u32,
+    pub created: i64,
+    pub last_seen: i64,
+    /// The `User-Agent` of the browser that logged in
+    pub user_agent: Option<String>,
+    /// Whether this is the session that asked for the list
+    pub current: bool,
 }
 
 /// A user that can be `Serialized` and `Deserialized`
@@ -30,6 +132,27 @@ impl User {
     }
 }
 
+/// A user's settings, stored on the server so they apply on every device
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Preferences {
+    /// Show posts and comments as they were written, instead of censoring inappropriate words
+    pub uncensored: bool,
+}
+
+/// What happens to a user's posts and comments when they delete their account
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletedContent {
+    /// Keep them, shown as written by [`DELETED_USERNAME`]
+    Anonymise,
+    /// Delete them, with every comment and AI job on the posts
+    Delete,
+}
+
+/// The author shown on anonymised posts and comments of deleted accounts.
+/// The brackets are not allowed in usernames, so nobody can register it.
+pub const DELETED_USERNAME: &str = "[deleted]";
+
 /// A post that can be `Serialized` and `Deserialized`
 ///
 /// `Post`s are sent and recieved by both `frontend` and `server`.
@@ -41,6 +164,9 @@ pub struct Post {
     pub username: String,
     pub content: String,
 
+    #[serde(default)]
+    pub author_kind: AuthorKind,
+
     /// Posts can have no comments.
     pub comments: Option<Vec<Comment>>,
 }
@@ -62,4 +188,281 @@ pub struct Comment {
 
     pub username: String,
     pub content: String,
+
+    #[serde(default)]
+    pub author_kind: AuthorKind,
+
+    /// Pinned comments are shown above the rest, like support resources on posts flagged as at risk.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// A piece of AI advice that is still being generated.
+///
+/// Sent as the `chunk` event of `/api/posts/:id/advice/stream`.
+/// Once generation is done, the finished [`Comment`] is sent as the `done` event.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdviceChunk {
+    pub comment_id: u32,
+    pub text: String,
+}
+
+/// A post flagged by the risk classifier, waiting for staff to review it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RiskReview {
+    pub id: u32,
+    pub post_id: u32,
+    pub created: i64,
+
+    /// The post's author
+    pub username: String,
+    /// The post's content
+    pub content: String,
+
+    pub score: u32,
+    /// The lexicon terms and rules that matched
+    pub matched: Vec<String>,
+
+    /// The staff member who reviewed the post, if anyone has
+    pub reviewed_by: Option<String>,
+}
+
+/// What a [`Report`] is about
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportTarget {
+    Post,
+    Comment,
+}
+
+impl ReportTarget {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportTarget::Post => "post",
+            ReportTarget::Comment => "comment",
+        }
+    }
+
+    /// Parse a name stored in the database. Unknown names are treated as [`ReportTarget::Post`].
+    #[must_use]
+    pub fn from_db(target: &str) -> Self {
+        match target {
+            "comment" => ReportTarget::Comment,
+            _ => ReportTarget::Post,
+        }
+    }
+}
+
+/// Why something was reported
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Harassment,
+    Hate,
+    Sexual,
+    SelfHarm,
+    Spam,
+    Other,
+}
+
+impl ReportReason {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportReason::Harassment => "harassment",
+            ReportReason::Hate => "hate",
+            ReportReason::Sexual => "sexual",
+            ReportReason::SelfHarm => "self_harm",
+            ReportReason::Spam => "spam",
+            ReportReason::Other => "other",
+        }
+    }
+
+    /// Parse a name stored in the database. Unknown names are treated as [`ReportReason::Other`].
+    #[must_use]
+    pub fn from_db(reason: &str) -> Self {
+        match reason {
+            "harassment" => ReportReason::Harassment,
+            "hate" => ReportReason::Hate,
+            "sexual" => ReportReason::Sexual,
+            "self_harm" => ReportReason::SelfHarm,
+            "spam" => ReportReason::Spam,
+            _ => ReportReason::Other,
+        }
+    }
+}
+
+/// A user's report of a post or comment, waiting for a moderator
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Report {
+    pub id: u32,
+    pub created: i64,
+
+    pub target: ReportTarget,
+    pub target_id: u32,
+    /// The reported post's or comment's author
+    pub author: String,
+    /// The reported post's or comment's content
+    pub content: String,
+    /// Whether the reported item is hidden from `get_posts`
+    pub hidden: bool,
+
+    /// The user who reported it
+    pub reporter: String,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+
+    /// How many open reports the reported item has, including this one
+    pub open_reports: u32,
+}
+
+/// Which custom word list a [`WordListEntry`] is on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WordList {
+    /// Always blocked, like local slang or names of specific people
+    Block,
+    /// Never blocked, for harmless words the filter gets wrong
+    Allow,
+}
+
+impl WordList {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WordList::Block => "block",
+            WordList::Allow => "allow",
+        }
+    }
+
+    /// Parse a name stored in the database. Unknown names are treated as [`WordList::Block`].
+    #[must_use]
+    pub fn from_db(list: &str) -> Self {
+        match list {
+            "allow" => WordList::Allow,
+            _ => WordList::Block,
+        }
+    }
+}
+
+/// A word on one of the custom word lists that are applied on top of the profanity filter
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WordListEntry {
+    pub id: u32,
+    pub created: i64,
+
+    pub word: String,
+    pub list: WordList,
+
+    /// The admin who added the word
+    pub added_by: String,
+}
+
+/// What happened in a [`ModerationEvent`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// The moderation filter rejected a post, comment or username
+    FilterRejected,
+    /// The spam filter rejected a post or comment
+    SpamRejected,
+    /// A username or IP address failed to log in too often, and is locked out for a while
+    LoginLockedOut,
+    /// A user reported a post or comment
+    Reported,
+    /// A post or comment got enough reports to be hidden
+    AutoHidden,
+    ReportResolved,
+    ReportDismissed,
+    RiskReviewed,
+    PostDeleted,
+    CommentDeleted,
+    Banned,
+    Unbanned,
+    Muted,
+    Unmuted,
+    ShadowBanned,
+    UnshadowBanned,
+    RoleChanged,
+    WordAdded,
+    WordRemoved,
+}
+
+impl ModerationAction {
+    /// The name stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModerationAction::FilterRejected => "filter_rejected",
+            ModerationAction::SpamRejected => "spam_rejected",
+            ModerationAction::LoginLockedOut => "login_locked_out",
+            ModerationAction::Reported => "reported",
+            ModerationAction::AutoHidden => "auto_hidden",
+            ModerationAction::ReportResolved => "report_resolved",
+            ModerationAction::ReportDismissed => "report_dismissed",
+            ModerationAction::RiskReviewed => "risk_reviewed",
+            ModerationAction::PostDeleted => "post_deleted",
+            ModerationAction::CommentDeleted => "comment_deleted",
+            ModerationAction::Banned => "banned",
+            ModerationAction::Unbanned => "unbanned",
+            ModerationAction::Muted => "muted",
+            ModerationAction::Unmuted => "unmuted",
+            ModerationAction::ShadowBanned => "shadow_banned",
+            ModerationAction::UnshadowBanned => "unshadow_banned",
+            ModerationAction::RoleChanged => "role_changed",
+            ModerationAction::WordAdded => "word_added",
+            ModerationAction::WordRemoved => "word_removed",
+        }
+    }
+
+    /// Parse a name stored in the database
+    #[must_use]
+    pub fn from_db(action: &str) -> Option<Self> {
+        Some(match action {
+            "filter_rejected" => ModerationAction::FilterRejected,
+            "spam_rejected" => ModerationAction::SpamRejected,
+            "login_locked_out" => ModerationAction::LoginLockedOut,
+            "reported" => ModerationAction::Reported,
+            "auto_hidden" => ModerationAction::AutoHidden,
+            "report_resolved" => ModerationAction::ReportResolved,
+            "report_dismissed" => ModerationAction::ReportDismissed,
+            "risk_reviewed" => ModerationAction::RiskReviewed,
+            "post_deleted" => ModerationAction::PostDeleted,
+            "comment_deleted" => ModerationAction::CommentDeleted,
+            "banned" => ModerationAction::Banned,
+            "unbanned" => ModerationAction::Unbanned,
+            "muted" => ModerationAction::Muted,
+            "unmuted" => ModerationAction::Unmuted,
+            "shadow_banned" => ModerationAction::ShadowBanned,
+            "unshadow_banned" => ModerationAction::UnshadowBanned,
+            "role_changed" => ModerationAction::RoleChanged,
+            "word_added" => ModerationAction::WordAdded,
+            "word_removed" => ModerationAction::WordRemoved,
+            _ => return None,
+        })
+    }
+}
+
+/// An entry in the moderation audit log
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModerationEvent {
+    pub id: u32,
+    pub created: i64,
+
+    /// Who did it: a username, or `system` for automated actions
+    pub actor: String,
+    pub action: ModerationAction,
+
+    /// What it was done to: `user`, `post`, `comment`, `risk_review` or `word`
+    pub target_kind: String,
+    /// The username, id or word it was done to
+    pub target: String,
+
+    pub reason: Option<String>,
+    /// Anything else worth keeping, like rejected content or when a mute ends
+    pub details: Option<String>,
 }
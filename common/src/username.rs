@@ -0,0 +1,155 @@
+//! Rules for usernames, shared by the server and the frontend.
+//!
+//! Usernames are normalised to Unicode NFKC before they are checked or stored, so that
+//! full-width letters, ligatures and other compatibility characters become the plain ones.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton, RestrictionLevel, RestrictionLevelDetection};
+
+use crate::RESERVED_USERNAMES;
+
+/// In characters, after normalisation
+pub const MIN_USERNAME_LENGTH: usize = 3;
+/// In characters, after normalisation
+pub const MAX_USERNAME_LENGTH: usize = 20;
+
+/// Allowed between letters and digits, but not at the start or end, or twice in a row
+const PUNCTUATION: &[char] = &['_', '-', '.'];
+
+/// Why a username was rejected
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UsernameError {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    /// Anything but letters, digits and `_ - .`, like spaces, emoji or invisible characters
+    InvalidCharacter {
+        character: char,
+    },
+    /// Punctuation at the start or end, or twice in a row
+    MisplacedPunctuation,
+    /// Letters from scripts that are not normally written together, like Latin and Cyrillic
+    MixedScripts,
+    /// Looks like one of the [`RESERVED_USERNAMES`]
+    Reserved,
+    /// Blocked by the server's moderation policy
+    Inappropriate,
+    /// Looks like the name of an existing account, only checked by the server
+    TooSimilar,
+    /// Only checked by the server
+    Taken,
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::TooShort { min } => {
+                write!(f, "username must be at least {min} characters")
+            }
+            UsernameError::TooLong { max } => {
+                write!(f, "username must be at most {max} characters")
+            }
+            UsernameError::InvalidCharacter { character } => {
+                write!(
+                    f,
+                    "username cannot contain {character:?}, only letters, digits, _, - and ."
+                )
+            }
+            UsernameError::MisplacedPunctuation => {
+                f.write_str("_, - and . must be between letters or digits")
+            }
+            UsernameError::MixedScripts => {
+                f.write_str("username cannot mix alphabets, like latin and cyrillic")
+            }
+            UsernameError::Reserved => f.write_str("that username is reserved"),
+            UsernameError::Inappropriate => f.write_str("that username is not allowed"),
+            UsernameError::TooSimilar => f.write_str("too similar to an existing username"),
+            UsernameError::Taken => f.write_str("user already exists"),
+        }
+    }
+}
+
+/// `username` without surrounding whitespace, in Unicode NFKC
+#[must_use]
+pub fn normalize_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+/// What `username` looks like, so that names which look alike, like `admin`, `AdMin` and `adm1n`,
+/// have the same skeleton. See [UTS #39](https://www.unicode.org/reports/tr39/#Confusable_Detection).
+#[must_use]
+pub fn username_skeleton(username: &str) -> String {
+    skeleton(&normalize_username(username).to_lowercase())
+        .collect::<String>()
+        .to_lowercase()
+        // `I` looks like `l`, which is lost once everything is lowercase
+        .replace('i', "l")
+}
+
+/// Check `username` against every rule that does not need the server.
+///
+/// Returns the normalised username, which is what should be stored.
+///
+/// # Errors
+/// The first rule that `username` breaks, see [`UsernameError`]
+pub fn validate_username(username: &str) -> Result<String, UsernameError> {
+    let username = normalize_username(username);
+    let length = username.chars().count();
+
+    if length < MIN_USERNAME_LENGTH {
+        return Err(UsernameError::TooShort {
+            min: MIN_USERNAME_LENGTH,
+        });
+    }
+
+    if length > MAX_USERNAME_LENGTH {
+        return Err(UsernameError::TooLong {
+            max: MAX_USERNAME_LENGTH,
+        });
+    }
+
+    if let Some(character) = username
+        .chars()
+        .find(|&c| !c.is_alphanumeric() && !PUNCTUATION.contains(&c))
+    {
+        return Err(UsernameError::InvalidCharacter { character });
+    }
+
+    let misplaced = username.starts_with(PUNCTUATION)
+        || username.ends_with(PUNCTUATION)
+        || username
+            .chars()
+            .zip(username.chars().skip(1))
+            .any(|(a, b)| PUNCTUATION.contains(&a) && PUNCTUATION.contains(&b));
+
+    if misplaced {
+        return Err(UsernameError::MisplacedPunctuation);
+    }
+
+    // still allows latin with chinese, japanese or korean
+    let letters: String = username
+        .chars()
+        .filter(|c| !PUNCTUATION.contains(c))
+        .collect();
+
+    if !letters.check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+        return Err(UsernameError::MixedScripts);
+    }
+
+    let skeleton = username_skeleton(&username);
+    if RESERVED_USERNAMES
+        .iter()
+        .any(|reserved| username_skeleton(reserved) == skeleton)
+    {
+        return Err(UsernameError::Reserved);
+    }
+
+    Ok(username)
+}
@@ -1,10 +1,14 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
 use chrono::{DateTime, Local, Utc};
 use frontend::{get_document, get_input, set_text, set_text_str};
+use futures::stream::{select_all, StreamExt};
+use gloo_net::eventsource::futures::EventSource;
 use gloo_net::http::{Request, Response};
 
 use gloo_storage::{LocalStorage, SessionStorage, Storage};
 use gloo_timers::future::TimeoutFuture;
-use rustrict::CensorStr;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 
@@ -14,7 +18,19 @@ use yew_router::prelude::*;
 
 use serde::Deserialize;
 
-use common::{inputs::InputComment, Post, User};
+use common::inputs::{InputComment, InputDeleteAccount, InputPasswordChange};
+use common::username::{
+    validate_username, UsernameError, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH,
+};
+use common::{AdviceChunk, AuthorKind, Comment, DeletedContent, Post, Preferences, Session, User};
+
+/// Content of an AI comment that is still being generated
+const LOADING: &str = "Loading, please wait!";
+
+thread_local! {
+    /// Posts whose advice is currently being streamed
+    static STREAMING: RefCell<HashSet<u32>> = RefCell::new(HashSet::new());
+}
 
 #[derive(Clone, Routable, PartialEq)]
 enum Route {
@@ -55,17 +71,21 @@ fn render_posts(document: &Document) {
     let posts_element = document.get_element_by_id("posts").unwrap();
     let stars = document.get_elements_by_class_name("star");
 
-    let should_censor = LocalStorage::get::<bool>("censor").is_err();
-
     // fetch posts on load
     spawn_local(async move {
-        let posts: Vec<Post> =
-            if let Ok(Some(post)) = get_api_json::<Option<Vec<Post>>>("/api/get_posts").await {
-                post
-            } else {
-                log::info!("got no posts");
-                return;
-            };
+        // shadow-banned users only see their own posts when signed in, and the server censors posts unless the user opted out
+        let posts = if let Ok(session) = LocalStorage::get::<String>("session") {
+            get_api_json_bearing::<Option<Vec<Post>>>("/api/get_posts", &session).await
+        } else {
+            get_api_json::<Option<Vec<Post>>>("/api/get_posts").await
+        };
+
+        let posts: Vec<Post> = if let Ok(Some(post)) = posts {
+            post
+        } else {
+            log::info!("got no posts");
+            return;
+        };
 
             
         SessionStorage::delete("opened");
@@ -73,6 +93,18 @@ fn render_posts(document: &Document) {
         let num_posts = posts.len();
         log::info!("got {num_posts} posts");
 
+        let loading: Vec<u32> = posts
+            .iter()
+            .filter(|post| {
+                post.comments.as_ref().is_some_and(|comments| {
+                    comments.iter().any(|comment| {
+                        comment.author_kind == AuthorKind::Assistant && comment.content == LOADING
+                    })
+                })
+            })
+            .map(|post| post.id)
+            .collect();
+
         let posts: String = posts
             .iter()
             .map(|post| {
@@ -80,16 +112,25 @@ fn render_posts(document: &Document) {
                     Some(comments) => comments
                         .iter()
                         .map(|comment| {
-                            let content = if should_censor {
-                                comment.content.censor()
+                            // pinned comments are support resources, so make them stand out
+                            let class = if comment.pinned {
+                                "pb-2 fw-bold fst-normal text-warning"
                             } else {
-                                comment.content.clone()
+                                "pb-2"
                             };
 
-                            format!(r#"<div class="pb-2" id=comment-{}>{}: {}</div>"#,
+                            // authors that are not users get a badge, so they cannot be mistaken for one
+                            let badge = match comment.author_kind {
+                                AuthorKind::User => "",
+                                AuthorKind::Assistant => r#" <span class="badge text-bg-info">assistant</span>"#,
+                                AuthorKind::System => r#" <span class="badge text-bg-warning">system</span>"#,
+                                AuthorKind::Moderator => r#" <span class="badge text-bg-danger">moderator</span>"#,
+                            };
+
+                            format!(r#"<div class="{class}" id=comment-{}>{}{badge}: {}</div>"#,
                                 comment.id,
                                 &comment.username,
-                                content,
+                                &comment.content,
                             )
                         })
                         .collect::<Vec<String>>()
@@ -97,17 +138,7 @@ fn render_posts(document: &Document) {
                     None => String::new(),
                 };
 
-                let timestamp: DateTime<Utc> = DateTime::from_timestamp(post.created, 0).unwrap();
-                let timestamp: String =
-                    DateTime::<Local>::from(timestamp)
-                        .format("%d/%m/%Y %H:%M")
-                        .to_string();
-
-                let content = if should_censor {
-                    post.content.censor()
-                } else {
-                    post.content.clone()
-                };
+                let timestamp: String = format_timestamp(post.created);
 
                 format!(
                     r#"
@@ -126,7 +157,7 @@ fn render_posts(document: &Document) {
                     </div>"#,
                     post.id,
                     &post.username,
-                    content,
+                    &post.content,
                     post.id,
                     "close",
                     post.id
@@ -137,6 +168,10 @@ fn render_posts(document: &Document) {
 
         posts_element.set_inner_html(&posts);
 
+        for post_id in loading {
+            stream_advice(post_id);
+        }
+
         for i in 0..10 {
             if i + 1 > num_posts {
                 stars
@@ -155,6 +190,73 @@ fn render_posts(document: &Document) {
     });
 }
 
+/// Show a post's AI advice in its card as it is generated, until it is done. The server censors it.
+fn stream_advice(post_id: u32) {
+    if !STREAMING.with(|streaming| streaming.borrow_mut().insert(post_id)) {
+        return;
+    }
+
+    spawn_local(async move {
+        let set_comment = |comment_id: u32, content: &str| {
+            let document = get_document();
+
+            // follow-up answers are not rendered yet, so add them to the end of the thread
+            let comment = document
+                .get_element_by_id(&format!("comment-{comment_id}"))
+                .or_else(|| {
+                    let comments = document.get_element_by_id(&format!("comments-{post_id}"))?;
+                    let comment = document.create_element("div").ok()?;
+                    comment.set_class_name("pb-2");
+                    comment.set_id(&format!("comment-{comment_id}"));
+                    comments.append_child(&comment).ok()?;
+
+                    Some(comment)
+                });
+
+            if let Some(comment) = comment {
+                comment.set_text_content(Some(&format!("AI: {content}")));
+            }
+        };
+
+        if let Ok(mut source) = EventSource::new(&format!("/api/posts/{post_id}/advice/stream")) {
+            let subscriptions = ["chunk", "reset", "done"]
+                .into_iter()
+                .map(|event| source.subscribe(event))
+                .collect::<Result<Vec<_>, _>>();
+
+            if let Ok(subscriptions) = subscriptions {
+                let mut events = select_all(subscriptions);
+                let mut advice = String::new();
+
+                // the stream stops at the first connection error
+                while let Some(Ok((event, message))) = events.next().await {
+                    let data = message.data().as_string().unwrap_or_default();
+
+                    match event.as_str() {
+                        "chunk" => {
+                            if let Ok(chunk) = serde_json::from_str::<AdviceChunk>(&data) {
+                                advice.push_str(&chunk.text);
+                                set_comment(chunk.comment_id, &advice);
+                            }
+                        }
+                        "reset" => advice.clear(),
+                        _ => {
+                            if let Ok(comment) = serde_json::from_str::<Comment>(&data) {
+                                set_comment(comment.id, &comment.content);
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+
+            source.close();
+        }
+
+        STREAMING.with(|streaming| streaming.borrow_mut().remove(&post_id));
+    });
+}
+
 async fn render_login_status() {
     if let Ok(session) = gloo_storage::LocalStorage::get::<String>("session") {
         if let Ok(Some(username)) =
@@ -169,6 +271,66 @@ async fn render_login_status() {
     }
 }
 
+/// Format a unix timestamp in local time, like posts are
+fn format_timestamp(timestamp: i64) -> String {
+    let timestamp: DateTime<Utc> = DateTime::from_timestamp(timestamp, 0).unwrap();
+    DateTime::<Local>::from(timestamp)
+        .format("%d/%m/%Y %H:%M")
+        .to_string()
+}
+
+/// List the signed-in user's sessions in the account panel
+fn render_sessions() {
+    spawn_local(async {
+        let Ok(session) = LocalStorage::get::<String>("session") else {
+            get_document()
+                .get_element_by_id("sessions")
+                .unwrap()
+                .set_inner_html("");
+            return;
+        };
+
+        let Ok(Some(sessions)) =
+            get_api_json_bearing::<Option<Vec<Session>>>("/api/sessions", &session).await
+        else {
+            set_text_str("s", "could not fetch sessions, log in again.");
+            return;
+        };
+
+        let sessions: String = sessions
+            .iter()
+            .map(|session| {
+                // the user agent comes from whoever logged in, so do not let it add html
+                let user_agent = session
+                    .user_agent
+                    .as_deref()
+                    .unwrap_or("unknown browser")
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;");
+
+                let current = if session.current {
+                    r#" <span class="badge text-bg-success">this device</span>"#
+                } else {
+                    ""
+                };
+
+                format!(
+                    r#"<li class="pb-2">#{}{current}: {user_agent}<br/><small>logged in {}, last used {}</small></li>"#,
+                    session.id,
+                    format_timestamp(session.created),
+                    format_timestamp(session.last_seen),
+                )
+            })
+            .collect::<Vec<String>>()
+            .concat();
+
+        get_document()
+            .get_element_by_id("sessions")
+            .unwrap()
+            .set_inner_html(&sessions);
+    });
+}
+
 #[allow(clippy::needless_pass_by_value, clippy::too_many_lines)]
 fn switch(routes: Route) -> Html {
     match routes {
@@ -183,12 +345,21 @@ fn switch(routes: Route) -> Html {
                             if let Some(check) = document.get_element_by_id("uncensor") {
                                 if let Ok(check) = check.dyn_into::<HtmlInputElement>() {
                                     // change setting states
-                                    if LocalStorage::get::<bool>("censor").is_ok() {
-                                        check.set_checked(true);
+                                    if let Ok(session) = LocalStorage::get::<String>("session") {
+                                        if let Ok(Some(preferences)) =
+                                            get_api_json_bearing::<Option<Preferences>>(
+                                                "/api/preferences",
+                                                &session,
+                                            )
+                                            .await
+                                        {
+                                            check.set_checked(preferences.uncensored);
+                                        }
                                     }
 
                                     // change login status
                                     render_login_status().await;
+                                    render_sessions();
 
                                     // fetch posts
                                     render_posts(&document);
@@ -213,13 +384,33 @@ fn switch(routes: Route) -> Html {
                     .unwrap()
                     .unchecked_into::<web_sys::HtmlInputElement>();
 
-                if ok.checked() {
-                    LocalStorage::set("censor", true).unwrap();
-                } else {
-                    LocalStorage::delete("censor");
-                }
+                let Ok(session) = LocalStorage::get::<String>("session") else {
+                    set_text_str("o", "log in to change settings");
+                    return;
+                };
 
-                render_posts(&get_document());
+                let preferences = Preferences {
+                    uncensored: ok.checked(),
+                };
+
+                spawn_local(async move {
+                    let resp = Request::put("/api/preferences")
+                        .header("authorization", &format!("Bearer {session}"))
+                        .json(&preferences)
+                        .unwrap()
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) if resp.ok() => {
+                            set_text_str("o", "saved!");
+                            render_posts(&get_document());
+                        }
+                        Ok(resp) if resp.status() == 401 => set_text_str("o", "log in again."),
+                        Ok(resp) => set_text("o", format!("unknown status {}", resp.status())),
+                        Err(err) => set_text("o", format!("request error: {err:?}")),
+                    }
+                });
             });
 
             let log_in: Callback<MouseEvent> = Callback::from(move |_| {
@@ -248,13 +439,18 @@ fn switch(routes: Route) -> Html {
                                     set_text_str("a", "logged in!");
 
                                     set_text("login-status", format!("signed in as {username}"));
+                                    render_sessions();
                                 } else {
                                     set_text_str("a", "no session fetched");
                                 }
                             } else {
                                 match resp.status() {
-                                    401 => set_text_str("a", "wrong password"),
-                                    404 => set_text_str("a", "user not found"),
+                                    401 => set_text_str("a", "wrong username or password"),
+                                    403 => set_text_str("a", "this account is banned"),
+                                    429 => set_text(
+                                        "a",
+                                        resp.text().await.unwrap_or_default().to_lowercase(),
+                                    ),
                                     500 => set_text_str("a", "internal server error"),
                                     _ => set_text_str("a", "unknown status"),
                                 }
@@ -276,6 +472,14 @@ fn switch(routes: Route) -> Html {
                     return;
                 }
 
+                let username = match validate_username(&username) {
+                    Ok(username) => username,
+                    Err(reason) => {
+                        set_text("a", reason.to_string());
+                        return;
+                    }
+                };
+
                 set_text_str("a", "working...");
 
                 spawn_local(async move {
@@ -293,12 +497,14 @@ fn switch(routes: Route) -> Html {
                                     set_text_str("a", "created!");
 
                                     set_text("login-status", format!("signed in as {username}"));
+                                    render_sessions();
                                 } else {
                                     set_text_str("a", "no session fetched");
                                 }
+                            } else if let Ok(reason) = resp.json::<UsernameError>().await {
+                                set_text("a", reason.to_string());
                             } else {
                                 match resp.status() {
-                                    409 => set_text_str("a", "user already exists"),
                                     500 => set_text_str("a", "internal server error"),
                                     _ => set_text_str("a", "unknown status"),
                                 }
@@ -317,9 +523,199 @@ fn switch(routes: Route) -> Html {
                     .confirm_with_message("Are you sure you want to log out?")
                     .unwrap()
                 {
-                    LocalStorage::delete("session");
-                    get_document().location().unwrap().reload().unwrap();
+                    spawn_local(async {
+                        // end the session on the server too, so the token cannot be used again
+                        if let Ok(session) = LocalStorage::get::<String>("session") {
+                            let _ = Request::post("/api/logout")
+                                .header("authorization", &format!("Bearer {session}"))
+                                .send()
+                                .await;
+                        }
+
+                        LocalStorage::delete("session");
+                        get_document().location().unwrap().reload().unwrap();
+                    });
+                }
+            });
+
+            let show_sessions: Callback<MouseEvent> = Callback::from(move |_| {
+                set_text_str("s", "");
+                render_sessions();
+            });
+
+            let sign_out_session: Callback<MouseEvent> = Callback::from(move |_| {
+                let Ok(id) = get_input("session_id").parse::<u32>() else {
+                    set_text_str("s", "no session # selected");
+                    return;
+                };
+
+                let Ok(session) = LocalStorage::get::<String>("session") else {
+                    set_text_str("s", "not logged in");
+                    return;
+                };
+
+                set_text_str("s", "working...");
+
+                spawn_local(async move {
+                    let resp = Request::delete(&format!("/api/sessions/{id}"))
+                        .header("authorization", &format!("Bearer {session}"))
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) if resp.ok() => {
+                            set_text("s", format!("signed out session #{id}."));
+                            render_sessions();
+                        }
+                        Ok(resp) if resp.status() == 401 => set_text_str("s", "log in again."),
+                        Ok(resp) if resp.status() == 404 => {
+                            set_text("s", format!("session #{id} does not exist."))
+                        }
+                        Ok(resp) => set_text("s", format!("unknown status {}", resp.status())),
+                        Err(err) => set_text("s", format!("request error: {err:?}")),
+                    }
+                });
+            });
+
+            let logout_everywhere: Callback<MouseEvent> = Callback::from(move |_| {
+                let Ok(session) = LocalStorage::get::<String>("session") else {
+                    set_text_str("s", "not logged in");
+                    return;
+                };
+
+                if !web_sys::window()
+                    .unwrap()
+                    .confirm_with_message("Log out on every device, including this one?")
+                    .unwrap()
+                {
+                    return;
+                }
+
+                spawn_local(async move {
+                    let resp = Request::delete("/api/sessions")
+                        .header("authorization", &format!("Bearer {session}"))
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) if resp.ok() || resp.status() == 401 => {
+                            LocalStorage::delete("session");
+                            get_document().location().unwrap().reload().unwrap();
+                        }
+                        Ok(resp) => set_text("s", format!("unknown status {}", resp.status())),
+                        Err(err) => set_text("s", format!("request error: {err:?}")),
+                    }
+                });
+            });
+
+            let change_password: Callback<MouseEvent> = Callback::from(move |_| {
+                let current_password = get_input("inputCurrentPassword");
+                let new_password = get_input("inputNewPassword");
+
+                if current_password.trim().is_empty() || new_password.trim().is_empty() {
+                    set_text_str("d", "cannot be empty");
+                    return;
+                }
+
+                let Ok(session) = LocalStorage::get::<String>("session") else {
+                    set_text_str("d", "not logged in");
+                    return;
+                };
+
+                set_text_str("d", "working...");
+
+                spawn_local(async move {
+                    let resp = Request::post("/api/account/password")
+                        .header("authorization", &format!("Bearer {session}"))
+                        .json(&InputPasswordChange {
+                            current_password,
+                            new_password,
+                        })
+                        .unwrap()
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) if resp.ok() => {
+                            set_text_str(
+                                "d",
+                                "password changed! you were logged out everywhere else.",
+                            );
+                            render_sessions();
+                        }
+                        Ok(resp) if resp.status() == 401 => set_text_str("d", "log in again."),
+                        Ok(resp) if resp.status() == 403 => {
+                            set_text_str("d", "wrong current password")
+                        }
+                        Ok(resp) => {
+                            set_text("d", resp.text().await.unwrap_or_default().to_lowercase())
+                        }
+                        Err(err) => set_text("d", format!("request error: {err:?}")),
+                    }
+                });
+            });
+
+            let delete_account: Callback<MouseEvent> = Callback::from(move |_| {
+                let password = get_input("inputCurrentPassword");
+                let delete_content = get_document()
+                    .get_element_by_id("deleteContent")
+                    .unwrap()
+                    .unchecked_into::<HtmlInputElement>()
+                    .checked();
+
+                if password.trim().is_empty() {
+                    set_text_str("d", "enter your current password");
+                    return;
+                }
+
+                let Ok(session) = LocalStorage::get::<String>("session") else {
+                    set_text_str("d", "not logged in");
+                    return;
+                };
+
+                let message = if delete_content {
+                    "Delete your account, with all of your posts and comments? This cannot be undone."
+                } else {
+                    "Delete your account? Your posts and comments will be kept without your name. This cannot be undone."
+                };
+
+                if !web_sys::window()
+                    .unwrap()
+                    .confirm_with_message(message)
+                    .unwrap()
+                {
+                    return;
                 }
+
+                let content = if delete_content {
+                    DeletedContent::Delete
+                } else {
+                    DeletedContent::Anonymise
+                };
+
+                set_text_str("d", "working...");
+
+                spawn_local(async move {
+                    let resp = Request::delete("/api/account")
+                        .header("authorization", &format!("Bearer {session}"))
+                        .json(&InputDeleteAccount { password, content })
+                        .unwrap()
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) if resp.ok() => {
+                            LocalStorage::delete("session");
+                            get_document().location().unwrap().reload().unwrap();
+                        }
+                        Ok(resp) if resp.status() == 401 => set_text_str("d", "log in again."),
+                        Ok(resp) if resp.status() == 403 => {
+                            set_text_str("d", "wrong current password")
+                        }
+                        Ok(resp) => set_text("d", format!("unknown status {}", resp.status())),
+                        Err(err) => set_text("d", format!("request error: {err:?}")),
+                    }
+                });
             });
 
             let create_post: Callback<MouseEvent> = Callback::from(move |_| {
@@ -356,7 +752,13 @@ fn switch(routes: Route) -> Html {
                             } else if resp.status() == 401 {
                                 set_text_str("c", "log in again.")
                             } else if resp.status() == 403 {
-                                set_text_str("b", "please use more appropriate language.");
+                                // either the filter or a mute, which says how long it lasts
+                                set_text("b", resp.text().await.unwrap_or_default().to_lowercase());
+                            } else if resp.status() == 429 {
+                                // posting too often, or repeating themselves, with how long to wait
+                                set_text("b", resp.text().await.unwrap_or_default().to_lowercase());
+                            } else if resp.status() == 503 {
+                                set_text_str("b", "the AI is busy, try again later.");
                             } else {
                                 set_text(
                                     "b",
@@ -406,20 +808,13 @@ fn switch(routes: Route) -> Html {
                     match resp {
                         Ok(resp) => {
                             if resp.ok() {
-                                set_text_str("c", "ok!");
+                                let message = resp.text().await.unwrap_or_default();
+                                set_text("c", message.to_lowercase());
 
                                 let comment_box = get_document()
                                     .get_element_by_id(&format!("comments-{post_id}"))
                                     .unwrap();
 
-                                let should_censor = LocalStorage::get::<bool>("censor").is_err();
-
-                                let content = if should_censor {
-                                    content.censor()
-                                } else {
-                                    content.clone()
-                                };
-    
                                 let new_comment = get_document()
                                     .create_element("div")
                                     .unwrap();
@@ -427,12 +822,25 @@ fn switch(routes: Route) -> Html {
                                 new_comment.set_text_content(Some(&format!("You: {content}")));
 
                                 comment_box.append_child(&new_comment).unwrap();
+
+                                if message == "OK, AI is answering" {
+                                    stream_advice(post_id);
+                                }
                             } else if resp.status() == 401 {
                                 set_text_str("c", "log in again.")
                             } else if resp.status() == 403 {
-                                set_text_str("c", "please use more appropriate language.");
+                                // either the filter or a mute, which says how long it lasts
+                                set_text("c", resp.text().await.unwrap_or_default().to_lowercase());
+                            } else if resp.status() == 429 {
+                                // posting too often, or repeating themselves, with how long to wait
+                                set_text("c", resp.text().await.unwrap_or_default().to_lowercase());
                             } else if resp.status() == 404 {
                                 set_text("c", format!("post {post_id} does not exist."));
+                            } else if resp.status() == 409 {
+                                set_text_str(
+                                    "c",
+                                    "the AI is still answering, wait for it to finish.",
+                                );
                             } else {
                                 set_text(
                                     "c",
@@ -445,6 +853,51 @@ fn switch(routes: Route) -> Html {
                 });
             });
 
+            let regenerate_advice: Callback<MouseEvent> = Callback::from(move |_| {
+                let post_id = get_input("comment_id").parse::<u32>();
+                let Ok(post_id) = post_id else {
+                    set_text_str("c", "no post # selected");
+                    return;
+                };
+
+                let session: String = format!(
+                    "Bearer {}",
+                    if let Ok(session) = LocalStorage::get::<String>("session") {
+                        set_text_str("c", "working...");
+                        session
+                    } else {
+                        set_text_str("c", "not logged in");
+                        return;
+                    }
+                );
+
+                spawn_local(async move {
+                    let resp = Request::post(&format!("/api/posts/{post_id}/regenerate_advice"))
+                        .header("authorization", &session)
+                        .send()
+                        .await;
+
+                    match resp {
+                        Ok(resp) => {
+                            if resp.ok() {
+                                set_text_str("c", "ok! the AI is answering again.");
+
+                                stream_advice(post_id);
+                            } else if resp.status() == 401 {
+                                set_text_str("c", "log in again.");
+                            } else if resp.status() == 403 {
+                                set_text_str("c", "only the author of a post can do that.");
+                            } else if resp.status() == 404 {
+                                set_text("c", format!("post {post_id} does not exist."));
+                            } else {
+                                set_text("c", resp.text().await.unwrap_or_default().to_lowercase());
+                            }
+                        }
+                        Err(err) => set_text("c", format!("request error: {err:?}")),
+                    };
+                });
+            });
+
             html! {
                 <>
 
@@ -472,6 +925,7 @@ fn switch(routes: Route) -> Html {
                             <div id="commentInfo" class="form-text">{ "Be mindful of what you comment!" }</div>
 
                             <button onclick={post_comment} class="btn btn-primary mt-2">{"Submit comment"}</button>
+                            <button onclick={regenerate_advice} class="btn btn-secondary mt-2 ms-3">{"Regenerate AI advice"}</button>
                             <p id="c"/>
                         </div>
                     </div>
@@ -536,7 +990,7 @@ fn switch(routes: Route) -> Html {
                                 <label for="inputUsername" class="form-label">{ "Username" }</label>
                                 <input type="text" placeholder="Type here" class="form-control" id="inputUsername" aria-describedby="usernameInfo"/>
 
-                                <div id="usernameInfo" class="form-text">{ "Usernames are unique!" }</div>
+                                <div id="usernameInfo" class="form-text">{ format!("Usernames are unique, {MIN_USERNAME_LENGTH} to {MAX_USERNAME_LENGTH} letters or digits, and can have _, - or . in between") }</div>
                             </div>
                             <div class="mb-3">
                                 <label for="inputPassword" class="form-label">{ "Password" }</label>
@@ -548,6 +1002,39 @@ fn switch(routes: Route) -> Html {
                             <p id="a"/>
                         </div>
 
+                        <div class="border rounded p-2 mb-2">
+                            <p>{ "Your sessions" }</p>
+                            <ul id="sessions" class="list-unstyled"/>
+
+                            <input type="number" min="0" id="session_id" placeholder="Session #" class="form-control"/>
+
+                            <button onclick={show_sessions} class="btn btn-secondary mt-2">{ "Refresh" }</button>
+                            <button onclick={sign_out_session} class="btn btn-primary mt-2 ms-3">{ "Sign out session" }</button>
+                            <button onclick={logout_everywhere} class="btn btn-danger mt-2 ms-3">{ "Log out everywhere" }</button>
+                            <p id="s"/>
+                        </div>
+
+                        <div class="border rounded p-2 mb-2">
+                            <div class="mb-3">
+                                <label for="inputCurrentPassword" class="form-label">{ "Current password" }</label>
+                                <input type="password" class="form-control" id="inputCurrentPassword" placeholder="Type here"/>
+                            </div>
+                            <div class="mb-3">
+                                <label for="inputNewPassword" class="form-label">{ "New password" }</label>
+                                <input type="password" class="form-control" id="inputNewPassword" placeholder="Type here"/>
+                            </div>
+
+                            <button onclick={change_password} class="btn btn-primary">{ "Change password" }</button>
+
+                            <div class="mt-3 form-check">
+                                <input type="checkbox" class="form-check-input" id="deleteContent" name="deleteContent"/>
+                                <label for="deleteContent" class="form-check-label">{ "Also delete my posts and comments, instead of removing my name from them" }</label>
+                            </div>
+
+                            <button onclick={delete_account} class="btn btn-danger mt-2">{ "Delete account" }</button>
+                            <p id="d"/>
+                        </div>
+
                         <h1 class="mb-3 text-center">{ "Options" }</h1>
 
                         <div class="border rounded p-2">
@@ -557,6 +1044,7 @@ fn switch(routes: Route) -> Html {
                             </div>
 
                             <button onclick={save_options} class="btn btn-primary">{ "Apply settings" }</button>
+                            <p id="o"/>
                         </div>
                     </div>
                 </div>
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::advice::validate::OutputRules;
+use crate::moderation::Moderation;
+
+pub mod canned;
+#[cfg(feature = "g4f")]
+pub mod g4f;
+pub mod hub;
+pub mod openai;
+pub mod pool;
+pub mod prompt;
+pub mod validate;
+
+/// Who sent a [`ChatMessage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// The role name used by OpenAI-style chat APIs
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// A single message of a chat completion request
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    #[must_use]
+    pub fn new(role: Role, content: &str) -> Self {
+        Self {
+            role,
+            content: content.to_string(),
+        }
+    }
+}
+
+/// A backend that can turn a conversation into advice.
+///
+/// Implementations are blocking, so they must not be called directly from an async task.
+pub trait AdviceProvider: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Get a single completion for `messages`.
+    ///
+    /// # Errors
+    /// Errors if the backend could not produce a response.
+    fn complete(&self, messages: &[ChatMessage]) -> anyhow::Result<String>;
+
+    /// Like [`AdviceProvider::complete`], but also passes each chunk of the response to `on_chunk`
+    /// as soon as it is produced.
+    ///
+    /// Providers that cannot stream send the whole response as one chunk.
+    ///
+    /// # Errors
+    /// Errors if the backend could not produce a response.
+    fn stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        let res = self.complete(messages)?;
+        on_chunk(&res);
+
+        Ok(res)
+    }
+}
+
+/// The providers that can be selected with `--advice-provider`
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ProviderKind {
+    /// the python `g4f` module, through pyo3
+    G4f,
+    /// any OpenAI-compatible chat completions API
+    Openai,
+    /// deterministic canned responses, for development
+    Canned,
+}
+
+/// Settings needed to build any of the [`ProviderKind`]s
+#[derive(Debug, Clone)]
+pub struct ProviderOpts {
+    pub openai_url: String,
+    pub openai_model: String,
+    pub openai_key: Option<String>,
+
+    /// How long a single call may take, where the backend supports it
+    pub timeout: Duration,
+}
+
+/// Build a provider chosen at startup.
+///
+/// # Errors
+/// Errors if the chosen provider is not available in this build.
+pub fn build_provider(
+    kind: ProviderKind,
+    opts: &ProviderOpts,
+) -> anyhow::Result<Arc<dyn AdviceProvider>> {
+    let provider: Arc<dyn AdviceProvider> = match kind {
+        #[cfg(feature = "g4f")]
+        ProviderKind::G4f => Arc::new(g4f::G4fProvider::new()),
+        #[cfg(not(feature = "g4f"))]
+        ProviderKind::G4f => anyhow::bail!("server was built without the `g4f` feature"),
+        ProviderKind::Openai => Arc::new(openai::OpenAiProvider::new(
+            &opts.openai_url,
+            &opts.openai_model,
+            opts.openai_key.clone(),
+            opts.timeout,
+        )),
+        ProviderKind::Canned => Arc::new(canned::CannedProvider),
+    };
+
+    tracing::info!("using advice provider {}", provider.name());
+
+    Ok(provider)
+}
+
+/// A piece of a response being streamed by [`get_advice`]
+#[derive(Debug, Clone, Copy)]
+pub enum Chunk<'a> {
+    Text(&'a str),
+    /// The response so far was thrown away, and another one may follow
+    Reset,
+}
+
+/// Ask each of `providers` in turn to respond to `messages`, until one response passes `rules` and `moderation`.
+///
+/// Chunks of the response are passed to `on_chunk` as they arrive, unless they already fail a check.
+/// If every response was rejected, [`OutputRules::fallback_message`] is returned instead.
+///
+/// # Errors
+/// Errors if no provider gave a response at all.
+pub fn get_advice(
+    providers: &[Arc<dyn AdviceProvider>],
+    rules: &OutputRules,
+    moderation: &Moderation,
+    messages: &[ChatMessage],
+    on_chunk: &mut dyn FnMut(Chunk),
+) -> anyhow::Result<String> {
+    let mut last_error = None;
+    let mut any_rejected = false;
+
+    for provider in providers {
+        let mut streamed = String::new();
+        let mut rejected = false;
+
+        let res = provider.stream(messages, &mut |text| {
+            if rejected {
+                return;
+            }
+
+            streamed.push_str(text);
+            if rules.validate_partial(&streamed, moderation).is_err() {
+                rejected = true;
+                on_chunk(Chunk::Reset);
+            } else {
+                on_chunk(Chunk::Text(text));
+            }
+        });
+
+        match res {
+            Ok(res) => match rules.validate(&res, moderation) {
+                Ok(()) => return Ok(res),
+                Err(rejection) => {
+                    tracing::warn!("ai output from {} rejected: {rejection}", provider.name());
+                    if !rejected {
+                        on_chunk(Chunk::Reset);
+                    }
+                    any_rejected = true;
+                }
+            },
+            Err(err) => {
+                tracing::error!("ai error ({}): {err}", provider.name());
+                if !streamed.is_empty() && !rejected {
+                    on_chunk(Chunk::Reset);
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+
+    match last_error {
+        // only errors, so it is worth trying again later
+        Some(err) if !any_rejected => Err(err),
+        _ => Ok(rules.fallback_message.clone()),
+    }
+}
@@ -0,0 +1,50 @@
+use std::thread;
+use std::time::Duration;
+
+use super::{AdviceProvider, ChatMessage};
+
+const RESPONSES: &[&str] = &[
+    "It sounds like you have a lot on your mind. Try writing down what is worrying you most, then pick one small step you can take today. Talking it through with someone you trust can also make it feel lighter.",
+    "Be kind to yourself, you are doing better than you think. Make sure you are sleeping, eating and taking short breaks. If things keep feeling heavy, reaching out to a friend or a counsellor is a strong thing to do.",
+    "Try to separate what you can control from what you cannot. Focus your energy on the first, and give yourself permission to let go of the second. Small routines, like a daily walk, can help a lot.",
+    "It is okay to feel this way. Give yourself some time, and try to share how you feel with someone close to you. You do not have to figure everything out alone.",
+];
+
+/// Delay between streamed words, so that streaming can be seen in the frontend
+const WORD_DELAY: Duration = Duration::from_millis(40);
+
+/// Always answers with one of a few fixed responses, picked deterministically from the conversation.
+///
+/// Useful for running the app without python or network access.
+pub struct CannedProvider;
+
+impl AdviceProvider for CannedProvider {
+    fn name(&self) -> &'static str {
+        "canned"
+    }
+
+    fn complete(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        let seed: usize = messages
+            .iter()
+            .flat_map(|message| message.content.bytes())
+            .map(usize::from)
+            .sum();
+
+        Ok(RESPONSES[seed % RESPONSES.len()].to_string())
+    }
+
+    fn stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        let res = self.complete(messages)?;
+
+        for word in res.split_inclusive(' ') {
+            on_chunk(word);
+            thread::sleep(WORD_DELAY);
+        }
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,85 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+use super::{AdviceProvider, ChatMessage};
+
+/// Uses the python `g4f` module through pyo3.
+pub struct G4fProvider;
+
+impl G4fProvider {
+    /// Prepares the embedded python interpreter.
+    #[must_use]
+    pub fn new() -> Self {
+        pyo3::prepare_freethreaded_python();
+        tracing::debug!("python ready");
+
+        Self
+    }
+}
+
+impl Default for G4fProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn create_message<'a>(py: Python<'a>, message: &ChatMessage) -> PyResult<&'a PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("role", message.role.as_str())?;
+    dict.set_item("content", &message.content)?;
+
+    Ok(dict)
+}
+
+fn create<'a>(py: Python<'a>, messages: &[ChatMessage], stream: bool) -> PyResult<&'a PyAny> {
+    let chat: &PyAny = py.import("g4f")?.getattr("ChatCompletion")?;
+
+    let messages = messages
+        .iter()
+        .map(|message| create_message(py, message))
+        .collect::<PyResult<Vec<&PyDict>>>()?;
+    let messages: &PyList = PyList::new(py, messages);
+
+    let build_args: &PyDict = PyDict::new(py);
+    build_args.set_item("messages", messages)?;
+    build_args.set_item("stream", stream)?;
+
+    chat.call_method("create", ("gpt-3.5-turbo",), Some(build_args))
+}
+
+impl AdviceProvider for G4fProvider {
+    fn name(&self) -> &'static str {
+        "g4f"
+    }
+
+    fn complete(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        Python::with_gil(|py| {
+            let run = || -> PyResult<String> { Ok(create(py, messages, false)?.to_string()) };
+
+            run().map_err(|err| anyhow::anyhow!("{}", err.value(py)))
+        })
+    }
+
+    fn stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        Python::with_gil(|py| {
+            let mut run = || -> PyResult<String> {
+                let mut res = String::new();
+
+                // with `stream=True`, g4f returns a generator of strings
+                for chunk in create(py, messages, true)?.iter()? {
+                    let chunk: String = chunk?.extract()?;
+                    on_chunk(&chunk);
+                    res.push_str(&chunk);
+                }
+
+                Ok(res)
+            };
+
+            run().map_err(|err| anyhow::anyhow!("{}", err.value(py)))
+        })
+    }
+}
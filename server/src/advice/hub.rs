@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use common::{AdviceChunk, Comment};
+use tokio::sync::broadcast;
+
+/// How many events a slow subscriber may fall behind before it misses some
+const CAPACITY: usize = 256;
+
+/// An event sent to the subscribers of a post
+#[derive(Debug, Clone)]
+pub enum AdviceEvent {
+    Chunk(AdviceChunk),
+    /// An attempt failed, chunks sent so far should be thrown away
+    Reset,
+    /// The AI comment was updated in the database
+    Done(Comment),
+}
+
+/// Broadcasts advice to everyone streaming it, per post.
+#[derive(Clone, Default)]
+pub struct AdviceHub {
+    channels: Arc<Mutex<HashMap<u32, broadcast::Sender<AdviceEvent>>>>,
+}
+
+impl AdviceHub {
+    fn channel(&self, post_id: u32) -> broadcast::Sender<AdviceEvent> {
+        self.channels
+            .lock()
+            .unwrap()
+            .entry(post_id)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .clone()
+    }
+
+    /// Get the sender for `post_id`'s events.
+    #[must_use]
+    pub fn sender(&self, post_id: u32) -> broadcast::Sender<AdviceEvent> {
+        self.channel(post_id)
+    }
+
+    /// Listen to `post_id`'s events.
+    #[must_use]
+    pub fn subscribe(&self, post_id: u32) -> broadcast::Receiver<AdviceEvent> {
+        self.channel(post_id).subscribe()
+    }
+
+    /// Send the final event of `post_id` and forget its channel.
+    pub fn finish(&self, post_id: u32, comment: Comment) {
+        if let Some(sender) = self.channels.lock().unwrap().remove(&post_id) {
+            let _ = sender.send(AdviceEvent::Done(comment));
+        }
+    }
+
+    /// Forget `post_id`'s channel if nothing is listening to it anymore.
+    pub fn release(&self, post_id: u32) {
+        let mut channels = self.channels.lock().unwrap();
+
+        if channels
+            .get(&post_id)
+            .is_some_and(|sender| sender.receiver_count() == 0)
+        {
+            channels.remove(&post_id);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use super::{AdviceProvider, ChatMessage};
+
+/// Talks to any OpenAI-compatible `/chat/completions` endpoint,
+/// such as the official API or a local stand-in.
+pub struct OpenAiProvider {
+    url: String,
+    model: String,
+    key: Option<String>,
+
+    agent: ureq::Agent,
+}
+
+impl OpenAiProvider {
+    /// `base_url` is the API root, e.g. `http://localhost:8000/v1`.
+    #[must_use]
+    pub fn new(base_url: &str, model: &str, key: Option<String>, timeout: Duration) -> Self {
+        Self {
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            key,
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+}
+
+impl AdviceProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn complete(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        let response: Value = self
+            .request()
+            .send_json(self.body(messages, false))?
+            .into_json()?;
+
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .context("response had no message content")
+    }
+
+    fn stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        let response = self.request().send_json(self.body(messages, true))?;
+
+        let mut res = String::new();
+
+        // the response is a server-sent event stream of `data: {json}` lines, ending with `data: [DONE]`
+        for line in BufReader::new(response.into_reader()).lines() {
+            let line = line?;
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+
+            let chunk: Value = serde_json::from_str(data)?;
+            if let Some(content) = chunk["choices"][0]["delta"]["content"].as_str() {
+                on_chunk(content);
+                res.push_str(content);
+            }
+        }
+
+        if res.is_empty() {
+            anyhow::bail!("stream had no message content");
+        }
+
+        Ok(res)
+    }
+}
+
+impl OpenAiProvider {
+    fn request(&self) -> ureq::Request {
+        let request = self.agent.post(&self.url);
+
+        match &self.key {
+            Some(key) => request.set("Authorization", &format!("Bearer {key}")),
+            None => request,
+        }
+    }
+
+    fn body(&self, messages: &[ChatMessage], stream: bool) -> Value {
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|message| json!({ "role": message.role.as_str(), "content": message.content }))
+            .collect();
+
+        json!({ "model": self.model, "messages": messages, "stream": stream })
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// The error of [`AdvicePool::run`] when every thread is busy, so `call` was never tried
+#[derive(Debug)]
+pub struct Busy;
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "every ai worker is busy")
+    }
+}
+
+impl std::error::Error for Busy {}
+
+/// A fixed set of dedicated threads for blocking AI calls,
+/// so that providers never run on (and starve) the tokio workers.
+#[derive(Clone)]
+pub struct AdvicePool {
+    sender: SyncSender<Task>,
+    timeout: Duration,
+}
+
+impl AdvicePool {
+    /// Start `workers` threads. Each call waits at most `timeout` for its result.
+    ///
+    /// # Panics
+    /// Panics if a thread could not be spawned.
+    #[must_use]
+    pub fn new(workers: usize, timeout: Duration) -> Self {
+        // only hand-offs are buffered, queueing happens in the `ai_jobs` table
+        let (sender, receiver) = mpsc::sync_channel::<Task>(workers);
+        let receiver: Arc<Mutex<Receiver<Task>>> = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("ai-worker-{i}"))
+                .spawn(move || loop {
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        Ok(task) => task(),
+                        // the pool was dropped
+                        Err(_) => break,
+                    }
+                })
+                .unwrap();
+        }
+
+        Self { sender, timeout }
+    }
+
+    /// Run `call` on one of the pool's threads.
+    ///
+    /// # Errors
+    /// Errors with [`Busy`] if every thread is busy, if `call` took longer than the timeout,
+    /// or if `call` itself errored or panicked.
+    /// A call that timed out keeps its thread until it returns, but its result is dropped.
+    pub async fn run<T, F>(&self, call: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        // a panicking provider must not take its thread down with it
+        let task: Task = Box::new(move || {
+            let res = panic::catch_unwind(AssertUnwindSafe(call))
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("ai provider panicked")));
+            let _ = sender.send(res);
+        });
+
+        match self.sender.try_send(task) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => return Err(Busy.into()),
+            Err(TrySendError::Disconnected(_)) => anyhow::bail!("ai workers are gone"),
+        }
+
+        match tokio::time::timeout(self.timeout, receiver).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => anyhow::bail!("ai worker panicked"),
+            Err(_) => anyhow::bail!("ai call timed out after {:?}", self.timeout),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Deserialize;
+
+use super::{ChatMessage, Role};
+
+/// The variables a [`PromptTemplate`] can use, written as `{name}`. Write `{{` and `}}` for literal braces.
+pub const VARIABLES: &[&str] = &["post", "username"];
+
+/// A versioned prompt, rendered into the first messages of a conversation
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptTemplate {
+    /// Stored with every AI comment made with this template
+    pub version: String,
+    /// Sent as a system message, if set
+    pub system: Option<String>,
+    /// Sent as the first user message
+    pub user: String,
+}
+
+/// Every prompt the AI is given, by what it is used for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Prompts {
+    /// Asks for advice on a post
+    pub advice: PromptTemplate,
+    /// Starts a follow-up conversation, the comment thread is sent after it
+    pub followup: PromptTemplate,
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        Self {
+            advice: PromptTemplate {
+                version: "advice-1".to_string(),
+                system: None,
+                user: r#"Depending on this message "{post}", what advice would you give this person? Keep your advice under 4 sentences, but try to respond in depth. Only respond with the advice."#.to_string(),
+            },
+            followup: PromptTemplate {
+                version: "followup-1".to_string(),
+                system: Some("You are giving advice on a wellbeing wall. Answer the person who posted, keep your answers under 4 sentences.".to_string()),
+                user: r#"Depending on this message "{post}", what advice would you give this person? Only respond with the advice."#.to_string(),
+            },
+        }
+    }
+}
+
+impl Prompts {
+    /// Check that every template only uses known variables.
+    ///
+    /// # Errors
+    /// Errors with the first template that does not.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, template) in [("advice", &self.advice), ("followup", &self.followup)] {
+            for text in template.system.iter().chain([&template.user]) {
+                render(text, &[]).map_err(|err| anyhow::anyhow!("prompt {name}: {err}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PromptTemplate {
+    /// Render the template into messages, with `vars` as `(name, value)` pairs.
+    #[must_use]
+    pub fn messages(&self, vars: &[(&str, &str)]) -> Vec<ChatMessage> {
+        let mut messages = Vec::with_capacity(2);
+
+        if let Some(system) = &self.system {
+            messages.push(ChatMessage::new(Role::System, &render_lossy(system, vars)));
+        }
+        messages.push(ChatMessage::new(
+            Role::User,
+            &render_lossy(&self.user, vars),
+        ));
+
+        messages
+    }
+}
+
+/// Like [`render`], but templates are validated when loaded, so errors should not happen.
+fn render_lossy(text: &str, vars: &[(&str, &str)]) -> String {
+    render(text, vars).unwrap_or_else(|err| {
+        tracing::error!("could not render prompt: {err}");
+        text.to_string()
+    })
+}
+
+/// Replace each `{name}` in `text`. Known variables missing from `vars` become empty.
+fn render(text: &str, vars: &[(&str, &str)]) -> Result<String, String> {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                res.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                res.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("unclosed {{{name}")),
+                    }
+                }
+
+                if !VARIABLES.contains(&name.as_str()) {
+                    return Err(format!("unknown variable {{{name}}}"));
+                }
+
+                let value = vars
+                    .iter()
+                    .find(|(var, _)| *var == name)
+                    .map_or("", |(_, value)| value);
+                res.push_str(value);
+            }
+            '}' => return Err("unmatched }".to_string()),
+            c => res.push(c),
+        }
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::moderation::{ContentKind, Moderation, Verdict};
+
+/// Top level domains that make a word look like a link, even without `http://` or `www.`
+const LINK_TLDS: &[&str] = &[
+    "com", "net", "org", "co", "io", "ly", "xyz", "info", "biz", "me", "ai", "app", "gg", "link",
+];
+
+/// What AI output must look like before it is published as a comment
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputRules {
+    /// Shortest acceptable response, in characters
+    pub min_length: usize,
+    /// Longest acceptable response, in characters
+    pub max_length: usize,
+    /// Phrases that mean the AI refused or answered with boilerplate, matched case-insensitively
+    pub refusal_phrases: Vec<String>,
+    /// Domains that links may point to. Links to any other domain are treated as spam
+    pub allowed_domains: Vec<String>,
+    /// Published instead when no provider gives an acceptable response
+    pub fallback_message: String,
+}
+
+impl Default for OutputRules {
+    fn default() -> Self {
+        Self {
+            min_length: 20,
+            max_length: 1500,
+            refusal_phrases: [
+                "as an ai language model",
+                "as an ai model",
+                "i'm sorry, but i can't",
+                "i am sorry, but i cannot",
+                "i cannot assist with",
+                "i can't assist with",
+                "i'm unable to provide",
+                "chatbase.co",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            allowed_domains: Vec::new(),
+            fallback_message: "Thank you for sharing. It sounds like a lot to carry, and you do not have to carry it alone. Consider talking to someone you trust about how you feel.".to_string(),
+        }
+    }
+}
+
+/// Why AI output was rejected
+#[derive(Debug)]
+pub enum Rejection {
+    /// Failed the moderation policy for [`ContentKind::AiOutput`]
+    Inappropriate(Verdict),
+    TooShort(usize),
+    TooLong(usize),
+    /// Contained one of [`OutputRules::refusal_phrases`]
+    Refusal(String),
+    /// Linked to a domain outside [`OutputRules::allowed_domains`]
+    SpamLink(String),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Inappropriate(verdict) => write!(f, "inappropriate: {verdict}"),
+            Rejection::TooShort(len) => write!(f, "too short: {len} characters"),
+            Rejection::TooLong(len) => write!(f, "too long: {len} characters"),
+            Rejection::Refusal(phrase) => write!(f, "refusal or boilerplate: {phrase:?}"),
+            Rejection::SpamLink(link) => write!(f, "spam link: {link:?}"),
+        }
+    }
+}
+
+impl OutputRules {
+    /// Run every check on a finished response.
+    ///
+    /// # Errors
+    /// Errors with the first check that failed.
+    pub fn validate(&self, text: &str, moderation: &Moderation) -> Result<(), Rejection> {
+        self.validate_partial(text, moderation)?;
+
+        let len = text.trim().chars().count();
+        if len < self.min_length {
+            return Err(Rejection::TooShort(len));
+        }
+
+        let lowercase = text.to_lowercase();
+        if let Some(phrase) = self
+            .refusal_phrases
+            .iter()
+            .find(|phrase| lowercase.contains(&phrase.to_lowercase()))
+        {
+            return Err(Rejection::Refusal(phrase.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Run the checks that can already fail while a response is still being streamed.
+    ///
+    /// # Errors
+    /// Errors with the first check that failed.
+    pub fn validate_partial(&self, text: &str, moderation: &Moderation) -> Result<(), Rejection> {
+        let len = text.chars().count();
+        if len > self.max_length {
+            return Err(Rejection::TooLong(len));
+        }
+
+        let verdict = moderation.check(ContentKind::AiOutput, text);
+        if !verdict.is_allowed() {
+            return Err(Rejection::Inappropriate(verdict));
+        }
+
+        if let Some(link) = text.split_whitespace().find(|word| self.is_spam_link(word)) {
+            return Err(Rejection::SpamLink(link.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn is_spam_link(&self, word: &str) -> bool {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+
+        let host = word
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.");
+        let host = host.split(['/', '?', '#']).next().unwrap_or_default();
+
+        let looks_like_link = word.starts_with("http://")
+            || word.starts_with("https://")
+            || word.starts_with("www.")
+            || host
+                .rsplit_once('.')
+                .is_some_and(|(name, tld)| !name.is_empty() && LINK_TLDS.contains(&tld));
+
+        looks_like_link
+            && !self
+                .allowed_domains
+                .iter()
+                .any(|domain| host == domain.as_str() || host.ends_with(&format!(".{domain}")))
+    }
+}
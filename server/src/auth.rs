@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::TypedHeader;
+
+use chrono::Utc;
+use common::Role;
+use sqlx::{Pool, Sqlite};
+
+use server::verify_auth;
+
+/// The lowest [`Role`] an [`Authorized`] user must have
+pub trait MinRole {
+    const ROLE: Role;
+}
+
+pub struct ModeratorRole;
+
+impl MinRole for ModeratorRole {
+    const ROLE: Role = Role::Moderator;
+}
+
+pub struct AdminRole;
+
+impl MinRole for AdminRole {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Extracts the signed-in user, if their role is at least `R::ROLE`.
+///
+/// Rejects with `401 Unauthorized` if the session is invalid, or `403 Forbidden` if the role is too low.
+pub struct Authorized<R: MinRole> {
+    pub username: String,
+    pub role: Role,
+    _role: PhantomData<R>,
+}
+
+/// A moderator or admin
+pub type Moderator = Authorized<ModeratorRole>;
+
+/// An admin
+pub type Admin = Authorized<AdminRole>;
+
+/// The role of `username`, or [`Role::User`] if they do not exist.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn role_of(username: &str, db_pool: &Pool<Sqlite>) -> Result<Role, sqlx::error::Error> {
+    let role: Option<String> = sqlx::query_scalar("SELECT role FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(db_pool)
+        .await?;
+
+    Ok(role.map_or(Role::User, |role| Role::from_db(&role)))
+}
+
+/// How many seconds `username` stays muted for, if they are muted.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn muted_for(
+    username: &str,
+    db_pool: &Pool<Sqlite>,
+) -> Result<Option<i64>, sqlx::error::Error> {
+    let muted_until: Option<i64> =
+        sqlx::query_scalar("SELECT muted_until FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(db_pool)
+            .await?
+            .flatten();
+
+    let now = Utc::now().timestamp();
+
+    Ok(muted_until
+        .filter(|&until| until > now)
+        .map(|until| until - now))
+}
+
+/// Format `seconds` like `2h 5m`, for telling users how long they have to wait.
+#[must_use]
+pub fn format_remaining(seconds: i64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds}s"),
+        _ => format!("{hours}h {minutes}m"),
+    }
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for Authorized<R>
+where
+    Pool<Sqlite>: FromRef<S>,
+    S: Send + Sync,
+    R: MinRole,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let TypedHeader(auth) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()))?;
+
+        let db_pool = Pool::<Sqlite>::from_ref(state);
+
+        let session = verify_auth(&auth, &db_pool)
+            .await
+            .map_err(|_| (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()))?;
+
+        let role = role_of(&session.username, &db_pool)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")))?;
+
+        if role < R::ROLE {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("Must be {}", R::ROLE.as_str()),
+            ));
+        }
+
+        Ok(Self {
+            username: session.username,
+            role,
+            _role: PhantomData,
+        })
+    }
+}
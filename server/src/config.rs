@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::advice::prompt::Prompts;
+use crate::advice::validate::OutputRules;
+use crate::lockout::LockoutRules;
+use crate::moderation::Moderation;
+use crate::risk::RiskRules;
+use crate::spam::SpamRules;
+
+/// Settings read from the config file given with `--config`.
+///
+/// Every section is optional, missing ones use their defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub prompts: Prompts,
+    pub ai_output: OutputRules,
+    pub moderation: Moderation,
+    pub risk: RiskRules,
+    pub reports: ReportRules,
+    pub spam: SpamRules,
+    pub lockout: LockoutRules,
+}
+
+/// How user reports are handled
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportRules {
+    /// Posts and comments with this many open reports are hidden until a moderator handles them
+    pub hide_threshold: u32,
+    /// Longest accepted `details` of a report, in characters
+    pub max_details_length: usize,
+}
+
+impl Default for ReportRules {
+    fn default() -> Self {
+        Self {
+            hide_threshold: 3,
+            max_details_length: 500,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file at `path`, or use the defaults if there is none.
+    ///
+    /// # Errors
+    /// Errors if the file exists but could not be read or is invalid.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            tracing::warn!("no config file at {}, using defaults", path.display());
+            return Ok(Self::default());
+        }
+
+        let config: Self = toml::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("invalid config file {}", path.display()))?;
+
+        config.prompts.validate()?;
+
+        tracing::info!("loaded config from {}", path.display());
+
+        Ok(config)
+    }
+}
@@ -1,5 +1,289 @@
-use server::{DBComment, DBPost, DBUser};
-use sqlx::{sqlite::SqliteQueryResult, Pool, Row, Sqlite};
+use common::username::username_skeleton;
+use server::{DBComment, DBModerationEvent, DBPost, DBReport, DBRiskReview, DBUser, DBWord};
+use sqlx::{sqlite::SqliteQueryResult, Pool, Row, Sqlite, SqliteConnection};
+
+/// Create every table the server uses, if they do not exist yet.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn create_tables(db_connection: &mut SqliteConnection) -> Result<(), sqlx::error::Error> {
+    sqlx::query("CREATE TABLE IF NOT EXISTS posts (id INTEGER PRIMARY KEY, username TEXT NOT NULL, content TEXT NOT NULL, created INTEGER NOT NULL)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS comments (id INTEGER PRIMARY KEY, post_id INTEGER NOT NULL, username TEXT NOT NULL, content TEXT NOT NULL, created INTEGER NOT NULL)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS users (username TEXT PRIMARY KEY, hashed_password TEXT NOT NULL, created INTEGER NOT NULL)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    drop_plaintext_sessions(db_connection).await?;
+
+    // `token_hash` is the keyed hash of the token's secret, see `server::DBSession`
+    sqlx::query("CREATE TABLE IF NOT EXISTS sessions (id INTEGER PRIMARY KEY, token_hash BLOB NOT NULL, username TEXT NOT NULL, created INTEGER NOT NULL, last_seen INTEGER NOT NULL, expires INTEGER NOT NULL, user_agent TEXT)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS sessions_username ON sessions (username)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    // failed logins, counted by username and by ip address, see `server/src/lockout.rs`
+    sqlx::query("CREATE TABLE IF NOT EXISTS login_failures (id INTEGER PRIMARY KEY, created INTEGER NOT NULL, username TEXT NOT NULL, ip TEXT NOT NULL)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    for column in ["username", "ip"] {
+        sqlx::query(&format!("CREATE INDEX IF NOT EXISTS login_failures_{column} ON login_failures ({column}, created)"))
+            .execute(&mut *db_connection)
+            .await?;
+    }
+
+    // `kind` is one of `advice`, `followup` or `regeneration`, see `server::JobKind`
+    // `state` is one of `pending`, `running`, `done` or `failed`, see `server::JobState`
+    sqlx::query("CREATE TABLE IF NOT EXISTS ai_jobs (id INTEGER PRIMARY KEY, post_id INTEGER NOT NULL, comment_id INTEGER NOT NULL, kind TEXT NOT NULL DEFAULT 'advice', state TEXT NOT NULL, attempts INTEGER NOT NULL, last_error TEXT, run_after INTEGER NOT NULL, created INTEGER NOT NULL, updated INTEGER NOT NULL)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    add_column(db_connection, "comments", "prompt_version", "TEXT").await?;
+
+    add_column(
+        db_connection,
+        "comments",
+        "pinned",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+
+    // `author_kind` is one of `user`, `assistant`, `system` or `moderator`, see `common::AuthorKind`
+    add_column(
+        db_connection,
+        "posts",
+        "author_kind",
+        "TEXT NOT NULL DEFAULT 'user'",
+    )
+    .await?;
+
+    if add_column(
+        db_connection,
+        "comments",
+        "author_kind",
+        "TEXT NOT NULL DEFAULT 'user'",
+    )
+    .await?
+    {
+        reclassify_authors(db_connection).await?;
+    }
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS risk_reviews (id INTEGER PRIMARY KEY, post_id INTEGER NOT NULL, created INTEGER NOT NULL, score INTEGER NOT NULL, matched TEXT NOT NULL, reviewed_by TEXT, reviewed_at INTEGER)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    // `hidden` items are left out of `get_posts` until a moderator dismisses their reports
+    add_column(
+        db_connection,
+        "posts",
+        "hidden",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column(
+        db_connection,
+        "comments",
+        "hidden",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+
+    // `state` is one of `open`, `resolved` or `dismissed`, see `server::ReportState`
+    sqlx::query("CREATE TABLE IF NOT EXISTS reports (id INTEGER PRIMARY KEY, created INTEGER NOT NULL, target TEXT NOT NULL, target_id INTEGER NOT NULL, reporter TEXT NOT NULL, reason TEXT NOT NULL, details TEXT, state TEXT NOT NULL, handled_by TEXT, handled_at INTEGER, UNIQUE (target, target_id, reporter))")
+        .execute(&mut *db_connection)
+        .await?;
+
+    // `role` is one of `user`, `moderator` or `admin`, see `common::Role`
+    add_column(
+        db_connection,
+        "users",
+        "role",
+        "TEXT NOT NULL DEFAULT 'user'",
+    )
+    .await?;
+    add_column(
+        db_connection,
+        "users",
+        "banned",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column(db_connection, "users", "muted_until", "INTEGER").await?;
+    add_column(
+        db_connection,
+        "users",
+        "shadow_banned",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+
+    // what the username looks like, see `common::username::username_skeleton`
+    if add_column(
+        db_connection,
+        "users",
+        "skeleton",
+        "TEXT NOT NULL DEFAULT ''",
+    )
+    .await?
+    {
+        store_skeletons(db_connection).await?;
+    }
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS users_skeleton ON users (skeleton)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    add_column(
+        db_connection,
+        "users",
+        "uncensored",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+
+    // `list` is one of `block` or `allow`, see `common::WordList`
+    sqlx::query("CREATE TABLE IF NOT EXISTS word_lists (id INTEGER PRIMARY KEY, created INTEGER NOT NULL, word TEXT NOT NULL, list TEXT NOT NULL, added_by TEXT NOT NULL, UNIQUE (word, list))")
+        .execute(&mut *db_connection)
+        .await?;
+
+    // `action` is one of `common::ModerationAction`
+    sqlx::query("CREATE TABLE IF NOT EXISTS moderation_events (id INTEGER PRIMARY KEY, created INTEGER NOT NULL, actor TEXT NOT NULL, action TEXT NOT NULL, target_kind TEXT NOT NULL, target TEXT NOT NULL, reason TEXT, details TEXT)")
+        .execute(&mut *db_connection)
+        .await?;
+
+    // the audit log is append-only
+    for operation in ["UPDATE", "DELETE"] {
+        sqlx::query(&format!("CREATE TRIGGER IF NOT EXISTS moderation_events_no_{} BEFORE {operation} ON moderation_events BEGIN SELECT RAISE(ABORT, 'moderation_events is append-only'); END", operation.to_lowercase()))
+            .execute(&mut *db_connection)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Comments by the AI and the server used to be told apart from users' comments only by their username.
+///
+/// Before usernames were reserved, someone could have registered "AI" themselves,
+/// so in that case only comments that were filled in by an AI job are trusted.
+async fn reclassify_authors(
+    db_connection: &mut SqliteConnection,
+) -> Result<(), sqlx::error::Error> {
+    let ai_account_exists: bool =
+        sqlx::query_scalar("SELECT COUNT(*) > 0 FROM users WHERE username = 'AI'")
+            .fetch_one(&mut *db_connection)
+            .await?;
+
+    let assistant = if ai_account_exists {
+        tracing::warn!("an account named \"AI\" exists, only comments made by ai jobs are reclassified");
+
+        sqlx::query("UPDATE comments SET author_kind = 'assistant' WHERE username = 'AI' AND id IN (SELECT comment_id FROM ai_jobs)")
+    } else {
+        sqlx::query("UPDATE comments SET author_kind = 'assistant' WHERE username = 'AI'")
+    }
+    .execute(&mut *db_connection)
+    .await?
+    .rows_affected();
+
+    let system = sqlx::query(
+        "UPDATE comments SET author_kind = 'system' WHERE username = 'System' AND pinned = 1",
+    )
+    .execute(&mut *db_connection)
+    .await?
+    .rows_affected();
+
+    tracing::info!("reclassified {assistant} assistant and {system} system comments");
+
+    Ok(())
+}
+
+/// Fill in the skeleton of every account created before look-alike usernames were checked.
+///
+/// Existing accounts are kept even if they look alike, but new ones cannot look like either of them.
+async fn store_skeletons(db_connection: &mut SqliteConnection) -> Result<(), sqlx::error::Error> {
+    let usernames: Vec<String> = sqlx::query_scalar("SELECT username FROM users")
+        .fetch_all(&mut *db_connection)
+        .await?;
+
+    for username in &usernames {
+        sqlx::query("UPDATE users SET skeleton = $1 WHERE username = $2")
+            .bind(username_skeleton(username))
+            .bind(username)
+            .execute(&mut *db_connection)
+            .await?;
+    }
+
+    tracing::info!("stored the skeletons of {} usernames", usernames.len());
+
+    Ok(())
+}
+
+/// Session tokens used to be stored as they were, first in a table with one row per user, then in a `token` column.
+///
+/// They cannot be hashed without knowing the new token format's secret, so every one of them is ended,
+/// and everyone has to log in again.
+async fn drop_plaintext_sessions(
+    db_connection: &mut SqliteConnection,
+) -> Result<(), sqlx::error::Error> {
+    let plaintext: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pragma_table_info('sessions')) AND NOT EXISTS (SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'token_hash')")
+        .fetch_one(&mut *db_connection)
+        .await?;
+
+    if !plaintext {
+        return Ok(());
+    }
+
+    let ended: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions")
+        .fetch_one(&mut *db_connection)
+        .await?;
+
+    sqlx::query("DROP TABLE sessions")
+        .execute(&mut *db_connection)
+        .await?;
+
+    tracing::warn!(
+        "ended {ended} sessions with plaintext tokens, their users have to log in again"
+    );
+
+    Ok(())
+}
+
+/// Add a column to a table created by an older version of the server, unless it is already there.
+///
+/// Returns whether the column was added.
+async fn add_column(
+    db_connection: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, sqlx::error::Error> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info($1) WHERE name = $2")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&mut *db_connection)
+            .await?;
+
+    if !exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .execute(&mut *db_connection)
+        .await?;
+
+        tracing::info!("added column {table}.{column}");
+    }
+
+    Ok(!exists)
+}
 
 /// # Errors
 /// See [`sqlx::error::Error`]
@@ -7,12 +291,15 @@ pub async fn store_new_user(
     user: &DBUser,
     db_pool: &Pool<Sqlite>,
 ) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
-    sqlx::query("INSERT INTO users (username, hashed_password, created) VALUES ($1, $2, $3)")
-        .bind(&user.username)
-        .bind(&user.hashed_password)
-        .bind(user.created)
-        .execute(db_pool)
-        .await
+    sqlx::query(
+        "INSERT INTO users (username, hashed_password, created, skeleton) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&user.username)
+    .bind(&user.hashed_password)
+    .bind(user.created)
+    .bind(&user.skeleton)
+    .execute(db_pool)
+    .await
 }
 
 /// # Errors
@@ -21,11 +308,12 @@ pub async fn store_post(
     post: &DBPost,
     db_pool: &Pool<Sqlite>,
 ) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
-    sqlx::query("INSERT INTO posts (id, username, content, created) VALUES ($1, $2, $3, $4)")
+    sqlx::query("INSERT INTO posts (id, username, content, created, author_kind) VALUES ($1, $2, $3, $4, $5)")
         .bind(post.id)
         .bind(&post.username)
         .bind(&post.content)
         .bind(post.created)
+        .bind(&post.author_kind)
         .execute(db_pool)
         .await
 }
@@ -36,12 +324,85 @@ pub async fn store_comment(
     comment: &DBComment,
     db_pool: &Pool<Sqlite>,
 ) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
-    sqlx::query("INSERT INTO comments (id, post_id, username, content, created) VALUES ($1, $2, $3, $4, $5)")
+    sqlx::query("INSERT INTO comments (id, post_id, username, content, created, prompt_version, pinned, author_kind) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
         .bind(comment.id)
         .bind(comment.post_id)
         .bind(&comment.username)
         .bind(&comment.content)
         .bind(comment.created)
+        .bind(&comment.prompt_version)
+        .bind(comment.pinned)
+        .bind(&comment.author_kind)
+        .execute(db_pool)
+        .await
+}
+
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn store_risk_review(
+    review: &DBRiskReview,
+    db_pool: &Pool<Sqlite>,
+) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
+    sqlx::query(
+        "INSERT INTO risk_reviews (post_id, created, score, matched) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(review.post_id)
+    .bind(review.created)
+    .bind(review.score)
+    .bind(&review.matched)
+    .execute(db_pool)
+    .await
+}
+
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn store_report(
+    report: &DBReport,
+    db_pool: &Pool<Sqlite>,
+) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
+    sqlx::query("INSERT INTO reports (created, target, target_id, reporter, reason, details, state) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+        .bind(report.created)
+        .bind(&report.target)
+        .bind(report.target_id)
+        .bind(&report.reporter)
+        .bind(&report.reason)
+        .bind(&report.details)
+        .bind(report.state)
+        .execute(db_pool)
+        .await
+}
+
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn store_word(
+    word: &DBWord,
+    db_pool: &Pool<Sqlite>,
+) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
+    sqlx::query("INSERT INTO word_lists (created, word, list, added_by) VALUES ($1, $2, $3, $4)")
+        .bind(word.created)
+        .bind(&word.word)
+        .bind(&word.list)
+        .bind(&word.added_by)
+        .execute(db_pool)
+        .await
+}
+
+/// Append `event` to the moderation audit log.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn log_event(
+    event: &DBModerationEvent,
+    db_pool: &Pool<Sqlite>,
+) -> std::result::Result<SqliteQueryResult, sqlx::error::Error> {
+    sqlx::query("INSERT INTO moderation_events (created, actor, action, target_kind, target, reason, details) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+        .bind(event.created)
+        .bind(&event.actor)
+        .bind(&event.action)
+        .bind(&event.target_kind)
+        .bind(&event.target)
+        .bind(&event.reason)
+        .bind(&event.details)
         .execute(db_pool)
         .await
 }
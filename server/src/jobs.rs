@@ -0,0 +1,404 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use common::{AdviceChunk, AuthorKind, Comment};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::Notify;
+
+use crate::advice::hub::{AdviceEvent, AdviceHub};
+use crate::advice::pool::{AdvicePool, Busy};
+use crate::advice::prompt::Prompts;
+use crate::advice::validate::OutputRules;
+use crate::advice::{get_advice, AdviceProvider, ChatMessage, Chunk, Role};
+use crate::moderation::Moderation;
+use server::{DBComment, DBJob, DBPost, FromDBComment, JobKind, JobState};
+
+/// The content of an AI comment whose job has not finished yet
+pub const LOADING: &str = "Loading, please wait!";
+
+/// What a placeholder comment is replaced with when its job fails for good
+const FAILED: &str = "Error";
+
+/// How long an idle worker waits before looking for jobs again, in case it missed a notification
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Seconds to wait before retrying a failed attempt, multiplied by the number of attempts so far
+const RETRY_BACKOFF: i64 = 5;
+
+/// Seconds to wait before trying again when every AI thread was busy, which does not count as an attempt
+const BUSY_BACKOFF: i64 = 1;
+
+/// Seconds since a job was created after which every AI thread being busy counts as a failed attempt,
+/// so that jobs still fail in the end if the threads are stuck on calls that never return
+const BUSY_MAX_WAIT: i64 = 10 * 60;
+
+/// Limits on how much work the [`JobQueue`] takes on
+#[derive(Debug, Clone, Copy)]
+pub struct JobLimits {
+    /// Times a job is tried before it is marked as failed
+    pub max_attempts: u32,
+    /// Unfinished jobs allowed before new ones are refused
+    pub max_depth: u32,
+    /// Follow-up jobs allowed per post
+    pub max_followups: u32,
+    /// Regeneration jobs allowed per post
+    pub max_regenerations: u32,
+    /// Most recent comments of a thread given to the AI as context
+    pub max_thread: usize,
+}
+
+/// A queue of AI jobs stored in the `ai_jobs` table, so that no job is lost when the server restarts.
+#[derive(Clone)]
+pub struct JobQueue {
+    db_pool: Pool<Sqlite>,
+    /// Tried in order until one gives an acceptable response
+    providers: Arc<Vec<Arc<dyn AdviceProvider>>>,
+    pool: AdvicePool,
+    hub: AdviceHub,
+    prompts: Arc<Prompts>,
+    rules: Arc<OutputRules>,
+    moderation: Arc<Moderation>,
+    limits: JobLimits,
+
+    /// Wakes idle workers when a job is enqueued
+    notify: Arc<Notify>,
+}
+
+impl JobQueue {
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        db_pool: Pool<Sqlite>,
+        providers: Vec<Arc<dyn AdviceProvider>>,
+        pool: AdvicePool,
+        hub: AdviceHub,
+        prompts: Prompts,
+        rules: OutputRules,
+        moderation: Arc<Moderation>,
+        limits: JobLimits,
+    ) -> Self {
+        Self {
+            db_pool,
+            providers: Arc::new(providers),
+            pool,
+            hub,
+            prompts: Arc::new(prompts),
+            rules: Arc::new(rules),
+            moderation,
+            limits,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Whether there are too many unfinished jobs to accept another one.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn is_full(&self) -> Result<bool, sqlx::error::Error> {
+        let depth: u32 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_jobs WHERE state IN ($1, $2)")
+            .bind(JobState::Pending)
+            .bind(JobState::Running)
+            .fetch_one(&self.db_pool)
+            .await?;
+
+        Ok(depth >= self.limits.max_depth)
+    }
+
+    /// Whether post `post_id` can get another job of `kind`.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn allows(&self, post_id: u32, kind: JobKind) -> Result<bool, sqlx::error::Error> {
+        let limit = match kind {
+            JobKind::Advice => 1,
+            JobKind::Followup => self.limits.max_followups,
+            JobKind::Regeneration => self.limits.max_regenerations,
+        };
+
+        let jobs: u32 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM ai_jobs WHERE post_id = $1 AND kind = $2")
+                .bind(post_id)
+                .bind(kind)
+                .fetch_one(&self.db_pool)
+                .await?;
+
+        Ok(jobs < limit)
+    }
+
+    /// Whether post `post_id` has a job that is not finished yet.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn is_working_on(&self, post_id: u32) -> Result<bool, sqlx::error::Error> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM ai_jobs WHERE post_id = $1 AND state IN ($2, $3)",
+        )
+        .bind(post_id)
+        .bind(JobState::Pending)
+        .bind(JobState::Running)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Queue a job that fills in the placeholder comment `comment_id` on post `post_id`.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn enqueue(
+        &self,
+        post_id: u32,
+        comment_id: u32,
+        kind: JobKind,
+    ) -> Result<(), sqlx::error::Error> {
+        let now = Utc::now().timestamp();
+
+        sqlx::query("INSERT INTO ai_jobs (post_id, comment_id, kind, state, attempts, run_after, created, updated) VALUES ($1, $2, $3, $4, 0, $5, $5, $5)")
+            .bind(post_id)
+            .bind(comment_id)
+            .bind(kind)
+            .bind(JobState::Pending)
+            .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+
+        self.notify.notify_one();
+
+        Ok(())
+    }
+
+    /// Make jobs left over from a previous run claimable again.
+    ///
+    /// Jobs that were `running` when the server stopped are put back to `pending`,
+    /// and placeholder comments from before the queue existed get a job of their own.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn recover(&self) -> Result<(), sqlx::error::Error> {
+        let now = Utc::now().timestamp();
+
+        let stale = sqlx::query("UPDATE ai_jobs SET state = $1, updated = $2 WHERE state = $3")
+            .bind(JobState::Pending)
+            .bind(now)
+            .bind(JobState::Running)
+            .execute(&self.db_pool)
+            .await?
+            .rows_affected();
+
+        let orphaned = sqlx::query("INSERT INTO ai_jobs (post_id, comment_id, state, attempts, run_after, created, updated) SELECT post_id, id, $1, 0, $2, $2, $2 FROM comments WHERE author_kind = 'assistant' AND content = $3 AND id NOT IN (SELECT comment_id FROM ai_jobs)")
+            .bind(JobState::Pending)
+            .bind(now)
+            .bind(LOADING)
+            .execute(&self.db_pool)
+            .await?
+            .rows_affected();
+
+        tracing::info!("recovered {stale} stale and {orphaned} orphaned ai jobs");
+
+        Ok(())
+    }
+
+    /// Start `workers` tasks that claim and run jobs.
+    pub fn spawn_workers(&self, workers: usize) {
+        for worker in 0..workers {
+            let queue = self.clone();
+            tokio::spawn(async move { queue.work(worker).await });
+        }
+    }
+
+    async fn work(self, worker: usize) {
+        loop {
+            match self.claim().await {
+                Ok(Some(job)) => {
+                    tracing::debug!("ai worker {worker}: claimed job {}", job.id);
+                    if let Err(err) = self.run(&job).await {
+                        tracing::error!("ai worker {worker}: job {}: {err}", job.id);
+                    }
+                }
+                Ok(None) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.notify.notified()).await;
+                }
+                Err(err) => {
+                    tracing::error!("ai worker {worker}: could not claim job: {err}");
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Atomically mark the oldest claimable job as `running`.
+    async fn claim(&self) -> Result<Option<DBJob>, sqlx::error::Error> {
+        let now = Utc::now().timestamp();
+
+        sqlx::query_as::<_, DBJob>("UPDATE ai_jobs SET state = $1, attempts = attempts + 1, updated = $2 WHERE id = (SELECT id FROM ai_jobs WHERE state = $3 AND run_after <= $2 ORDER BY id LIMIT 1) RETURNING *")
+            .bind(JobState::Running)
+            .bind(now)
+            .bind(JobState::Pending)
+            .fetch_optional(&self.db_pool)
+            .await
+    }
+
+    /// Build the conversation for `job`, and get the version of the prompt it starts with.
+    async fn messages(
+        &self,
+        job: &DBJob,
+    ) -> Result<(Vec<ChatMessage>, String), sqlx::error::Error> {
+        let post = sqlx::query_as::<_, DBPost>("SELECT * FROM posts WHERE id = $1")
+            .bind(job.post_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+
+        let template = match job.kind {
+            JobKind::Advice | JobKind::Regeneration => &self.prompts.advice,
+            JobKind::Followup => &self.prompts.followup,
+        };
+        let mut messages =
+            template.messages(&[("post", &post.content), ("username", &post.username)]);
+
+        if job.kind != JobKind::Followup {
+            return Ok((messages, template.version.clone()));
+        }
+
+        // the thread as it was when the follow-up was asked, newest last,
+        // without comments hidden by reports or by other shadow-banned users, so that the AI cannot repeat them
+        let mut comments = sqlx::query_as::<_, DBComment>("SELECT * FROM comments WHERE post_id = $1 AND id < $2 AND content NOT IN ($3, $4) AND hidden = 0 AND (username = $6 OR username NOT IN (SELECT username FROM users WHERE shadow_banned = 1)) ORDER BY id DESC LIMIT $5")
+            .bind(job.post_id)
+            .bind(job.comment_id)
+            .bind(LOADING)
+            .bind(FAILED)
+            .bind(u32::try_from(self.limits.max_thread).unwrap_or(u32::MAX))
+            .bind(&post.username)
+            .fetch_all(&self.db_pool)
+            .await?;
+        comments.reverse();
+
+        let thread = comments
+            .iter()
+            .map(|comment| {
+                if comment.author_kind() == AuthorKind::Assistant {
+                    ChatMessage::new(Role::Assistant, &comment.content)
+                } else if comment.username == post.username {
+                    ChatMessage::new(Role::User, &comment.content)
+                } else {
+                    let content = format!("{} said: {}", comment.username, comment.content);
+                    ChatMessage::new(Role::User, &content)
+                }
+            })
+            .collect::<Vec<ChatMessage>>();
+        messages.extend(thread);
+
+        Ok((messages, template.version.clone()))
+    }
+
+    async fn run(&self, job: &DBJob) -> Result<(), sqlx::error::Error> {
+        let (messages, prompt_version) = self.messages(job).await?;
+
+        let providers = self.providers.clone();
+        let rules = self.rules.clone();
+        let moderation = self.moderation.clone();
+        let sender = self.hub.sender(job.post_id);
+        let comment_id = job.comment_id;
+
+        let response = self
+            .pool
+            .run(move || {
+                get_advice(&providers, &rules, &moderation, &messages, &mut |chunk| {
+                    let event = match chunk {
+                        Chunk::Text(text) => AdviceEvent::Chunk(AdviceChunk {
+                            comment_id,
+                            text: text.to_string(),
+                        }),
+                        Chunk::Reset => AdviceEvent::Reset,
+                    };
+                    let _ = sender.send(event);
+                })
+            })
+            .await;
+
+        match response {
+            Ok(response) => {
+                self.finish(job, JobState::Done, &response, Some(&prompt_version), None)
+                    .await?;
+                tracing::info!("post {}: ai done", job.post_id);
+            }
+            Err(err)
+                if err.is::<Busy>() && Utc::now().timestamp() - job.created < BUSY_MAX_WAIT =>
+            {
+                tracing::debug!("post {}: {err}, trying again later", job.post_id);
+
+                // claiming the job counted an attempt, but the AI was never called
+                let now = Utc::now().timestamp();
+                sqlx::query("UPDATE ai_jobs SET state = $2, attempts = attempts - 1, run_after = $3, updated = $4 WHERE id = $1")
+                    .bind(job.id)
+                    .bind(JobState::Pending)
+                    .bind(now + BUSY_BACKOFF)
+                    .bind(now)
+                    .execute(&self.db_pool)
+                    .await?;
+            }
+            Err(err) if job.attempts < self.limits.max_attempts => {
+                tracing::error!("ai error: {err}, retry {}", job.attempts);
+                let _ = self.hub.sender(job.post_id).send(AdviceEvent::Reset);
+
+                let now = Utc::now().timestamp();
+                sqlx::query("UPDATE ai_jobs SET state = $2, last_error = $3, run_after = $4, updated = $5 WHERE id = $1")
+                    .bind(job.id)
+                    .bind(JobState::Pending)
+                    .bind(err.to_string())
+                    .bind(now + RETRY_BACKOFF * i64::from(job.attempts))
+                    .bind(now)
+                    .execute(&self.db_pool)
+                    .await?;
+            }
+            Err(err) => {
+                self.finish(job, JobState::Failed, FAILED, None, Some(&err.to_string()))
+                    .await?;
+                tracing::error!(
+                    "post {}: ai failed after {} attempts: {err}",
+                    job.post_id,
+                    job.attempts
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the placeholder comment and close the job in one transaction,
+    /// then tell the post's subscribers.
+    async fn finish(
+        &self,
+        job: &DBJob,
+        state: JobState,
+        content: &str,
+        prompt_version: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::error::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+
+        sqlx::query("UPDATE comments SET content = $2, prompt_version = $3 WHERE id = $1")
+            .bind(job.comment_id)
+            .bind(content)
+            .bind(prompt_version)
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query("UPDATE ai_jobs SET state = $2, last_error = $3, updated = $4 WHERE id = $1")
+            .bind(job.id)
+            .bind(state)
+            .bind(error)
+            .bind(Utc::now().timestamp())
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        let comment = sqlx::query_as::<_, DBComment>("SELECT * FROM comments WHERE id = $1")
+            .bind(job.comment_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+        self.hub.finish(job.post_id, Comment::from_db(&comment));
+
+        Ok(())
+    }
+}
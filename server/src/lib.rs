@@ -1,14 +1,54 @@
+use std::sync::OnceLock;
+
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use axum::headers::{authorization::Bearer, Authorization};
 use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
 
-use common::{Comment, Post};
+use common::{AuthorKind, Comment, ModerationAction, Post};
 use sqlx::{FromRow, Pool, Sqlite};
 
-/// A user sesion
+/// Seconds a session stays valid without being used. Every use pushes its expiry back, see [`verify_auth`].
+pub const SESSION_IDLE_TIMEOUT: i64 = 60 * 60 * 24 * 14;
+
+/// Seconds a session stays valid at most, however often it is used
+pub const SESSION_MAX_AGE: i64 = 60 * 60 * 24 * 90;
+
+/// Seconds between writes of a session's `last_seen`, so that not every request writes to the database
+const SESSION_RENEW_INTERVAL: i64 = 60;
+
+/// Bytes in the key that session tokens are hashed with
+pub const SESSION_KEY_LENGTH: usize = 32;
+
+/// Random bytes in the secret part of a session token
+const SESSION_SECRET_LENGTH: usize = 32;
+
+/// Set once at startup by [`set_session_key`]
+static SESSION_KEY: OnceLock<[u8; SESSION_KEY_LENGTH]> = OnceLock::new();
+
+/// A logged-in device. Each user can have many sessions.
+///
+/// Its token, sent as the bearer of every authenticated request, is `<id>.<secret>`.
+/// Only a keyed hash of the secret is stored, so reading the database is not enough to log in as anyone.
 #[derive(Debug, FromRow)]
 pub struct DBSession {
+    pub id: u32,
+    /// The HMAC-SHA256 of the token's secret, see [`set_session_key`]
+    pub token_hash: Vec<u8>,
     pub username: String,
-    pub id: String,
+
+    pub created: i64,
+    pub last_seen: i64,
+    /// The session is rejected from this timestamp on
+    pub expires: i64,
+
+    /// The `User-Agent` of the browser that logged in, so users can tell their sessions apart
+    pub user_agent: Option<String>,
 }
 
 /// `User`s are never stored in database. Instead, `DBUser` is used since passwords are hashed before stored.
@@ -18,6 +58,19 @@ pub struct DBUser {
 
     pub username: String,
     pub hashed_password: String,
+
+    /// See [`common::Role::as_str`]
+    pub role: String,
+    /// Banned users cannot log in
+    pub banned: bool,
+    /// Muted users cannot post or comment before this timestamp
+    pub muted_until: Option<i64>,
+    /// Shadow-banned users' posts and comments are only shown to themselves
+    pub shadow_banned: bool,
+    /// See [`common::username::username_skeleton`]
+    pub skeleton: String,
+    /// See [`common::Preferences::uncensored`]
+    pub uncensored: bool,
 }
 
 /// `DBPost`s are individual posts without comments attached to them.
@@ -33,6 +86,9 @@ pub struct DBPost {
 
     pub username: String,
     pub content: String,
+
+    /// See [`AuthorKind::as_str`]
+    pub author_kind: String,
 }
 
 /// `DBComment`s are individual comments with an `id` and `post_id`.
@@ -47,9 +103,18 @@ pub struct DBComment {
 
     pub username: String,
     pub content: String,
+
+    /// See [`AuthorKind::as_str`]
+    pub author_kind: String,
+
+    /// The version of the prompt template that produced an AI comment
+    pub prompt_version: Option<String>,
+
+    pub pinned: bool,
 }
 
 impl DBComment {
+    /// A comment by a registered user
     #[must_use]
     pub fn new(id: u32, post_id: u32, username: &str, content: &str) -> Self {
         Self {
@@ -57,11 +122,169 @@ impl DBComment {
             post_id,
             username: username.to_string(),
             content: content.to_string(),
+            author_kind: AuthorKind::User.as_str().to_string(),
+            created: Utc::now().timestamp(),
+            prompt_version: None,
+            pinned: false,
+        }
+    }
+
+    /// A comment by an author that is not a registered user, like the AI
+    #[must_use]
+    pub fn new_system(id: u32, post_id: u32, author_kind: AuthorKind, content: &str) -> Self {
+        Self {
+            username: author_kind.display_name().unwrap_or_default().to_string(),
+            author_kind: author_kind.as_str().to_string(),
+            ..Self::new(id, post_id, "", content)
+        }
+    }
+
+    #[must_use]
+    pub fn author_kind(&self) -> AuthorKind {
+        AuthorKind::from_db(&self.author_kind)
+    }
+}
+
+/// A post flagged by the risk classifier. `matched` is a JSON array of strings.
+#[derive(Debug, FromRow)]
+pub struct DBRiskReview {
+    pub id: u32,
+    pub post_id: u32,
+    pub created: i64,
+
+    pub score: u32,
+    pub matched: String,
+
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<i64>,
+}
+
+/// The lifecycle of a [`DBJob`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a worker to claim it
+    Pending,
+    /// Claimed by a worker
+    Running,
+    /// The placeholder comment was replaced with the response
+    Done,
+    /// Every attempt failed, the placeholder comment was replaced with an error
+    Failed,
+}
+
+/// What a [`DBJob`] asks the AI for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum JobKind {
+    /// Advice on the post itself
+    Advice,
+    /// An answer to the post author, with the comment thread as context
+    Followup,
+    /// Advice on the post again, asked for by its author
+    Regeneration,
+}
+
+/// A queued AI job. Each job fills in the placeholder comment `comment_id` on post `post_id`.
+#[derive(Debug, FromRow)]
+pub struct DBJob {
+    pub id: u32,
+    pub post_id: u32,
+    pub comment_id: u32,
+    pub kind: JobKind,
+
+    pub state: JobState,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+
+    /// The job will not be claimed before this timestamp
+    pub run_after: i64,
+    pub created: i64,
+    pub updated: i64,
+}
+
+/// The lifecycle of a [`DBReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum ReportState {
+    /// Waiting for a moderator
+    Open,
+    /// A moderator agreed, the reported item stays hidden
+    Resolved,
+    /// A moderator disagreed, the reported item is shown again
+    Dismissed,
+}
+
+/// A user's report of a post or comment. Each user can report an item once.
+#[derive(Debug, FromRow, Clone)]
+pub struct DBReport {
+    pub id: u32,
+    pub created: i64,
+
+    /// See [`common::ReportTarget::as_str`]
+    pub target: String,
+    pub target_id: u32,
+
+    pub reporter: String,
+    /// See [`common::ReportReason::as_str`]
+    pub reason: String,
+    pub details: Option<String>,
+
+    pub state: ReportState,
+    /// The moderator who resolved or dismissed the report
+    pub handled_by: Option<String>,
+    pub handled_at: Option<i64>,
+}
+
+/// The actor of automated [`DBModerationEvent`]s. Nobody can register it, see [`common::RESERVED_USERNAMES`].
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// An entry in the append-only `moderation_events` table
+#[derive(Debug, FromRow, Clone)]
+pub struct DBModerationEvent {
+    pub id: u32,
+    pub created: i64,
+
+    pub actor: String,
+    /// See [`ModerationAction::as_str`]
+    pub action: String,
+
+    pub target_kind: String,
+    pub target: String,
+
+    pub reason: Option<String>,
+    pub details: Option<String>,
+}
+
+impl DBModerationEvent {
+    #[must_use]
+    pub fn new(actor: &str, action: ModerationAction, target_kind: &str, target: &str) -> Self {
+        Self {
+            id: 0,
             created: Utc::now().timestamp(),
+            actor: actor.to_string(),
+            action: action.as_str().to_string(),
+            target_kind: target_kind.to_string(),
+            target: target.to_string(),
+            reason: None,
+            details: None,
         }
     }
 }
 
+/// A word on a custom block or allow list
+#[derive(Debug, FromRow, Clone)]
+pub struct DBWord {
+    pub id: u32,
+    pub created: i64,
+
+    pub word: String,
+    /// See [`common::WordList::as_str`]
+    pub list: String,
+
+    pub added_by: String,
+}
+
 /// Convert from owned `DBPost` to `Post` by attaching comments.
 pub trait FromDBPost {
     fn from_db(post: DBPost, comments: Option<Vec<Comment>>) -> Self;
@@ -72,6 +295,7 @@ impl FromDBPost for Post {
         Self {
             id: post.id,
 
+            author_kind: AuthorKind::from_db(&post.author_kind),
             username: post.username,
             content: post.content,
             created: post.created,
@@ -95,25 +319,148 @@ impl FromDBComment for Comment {
             username: comment.username.clone(),
             content: comment.content.clone(),
             created: comment.created,
+            author_kind: comment.author_kind(),
+
+            pinned: comment.pinned,
         }
     }
 }
 
-/// Returns [`Ok(DBSession)`] if `session_id` was found in database, otherwise, return [`Err(sqlx::error::Error)`]
+/// Hash `password` with a new salt, to be stored as [`DBUser::hashed_password`].
+///
+/// # Panics
+/// Panics if `Argon2::default()` cannot hash, which it always can.
+#[must_use]
+pub fn hash_password(password: &str) -> String {
+    let salt: SaltString = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+/// Whether `password` is the one that `hashed_password` was made from
+#[must_use]
+pub fn password_matches(password: &str, hashed_password: &str) -> bool {
+    PasswordHash::new(hashed_password).is_ok_and(|hashed| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hashed)
+            .is_ok()
+    })
+}
+
+/// Set the key that session tokens are hashed with. Changing it ends every session.
+///
+/// # Panics
+/// Panics if the key was already set.
+pub fn set_session_key(key: [u8; SESSION_KEY_LENGTH]) {
+    assert!(
+        SESSION_KEY.set(key).is_ok(),
+        "the session key is already set"
+    );
+}
+
+/// The HMAC-SHA256 of a session token's `secret`
+///
+/// # Panics
+/// Panics if [`set_session_key`] was not called yet.
+fn hash_session_secret(secret: &str) -> Vec<u8> {
+    let key = SESSION_KEY
+        .get()
+        .expect("the session key is set at startup");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap(); // hmac takes keys of any length
+    mac.update(secret.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Start a new session for `username`, returning its token.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn create_session(
+    username: &str,
+    user_agent: Option<&str>,
+    db_pool: &Pool<Sqlite>,
+) -> Result<String, sqlx::error::Error> {
+    let mut secret = [0u8; SESSION_SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    let secret: String = secret.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    let now = Utc::now().timestamp();
+
+    let id: u32 = sqlx::query_scalar("INSERT INTO sessions (token_hash, username, created, last_seen, expires, user_agent) VALUES ($1, $2, $3, $3, $4, $5) RETURNING id")
+        .bind(hash_session_secret(&secret))
+        .bind(username)
+        .bind(now)
+        .bind(now + SESSION_IDLE_TIMEOUT)
+        .bind(user_agent)
+        .fetch_one(db_pool)
+        .await?;
+
+    Ok(format!("{id}.{secret}"))
+}
+
+/// Returns [`Ok(DBSession)`] if the bearer token was found in database and has not expired, otherwise, return [`Err(sqlx::error::Error)`]
+///
+/// The token's secret is hashed and compared to the stored hash in constant time.
+/// Using a session moves its expiry to [`SESSION_IDLE_TIMEOUT`] from now, but never past [`SESSION_MAX_AGE`] after it was created.
 ///
 /// # Errors
 ///
-/// Will always error if header is not found, otherwise, refer to [`sqlx::error::Error`]
+/// Will always error if header is not found, the token is wrong or the session expired, otherwise, refer to [`sqlx::error::Error`]
 pub async fn verify_auth(
     header: &Authorization<Bearer>,
     db_pool: &Pool<Sqlite>,
 ) -> Result<DBSession, sqlx::error::Error> {
-    let res = sqlx::query_as::<_, DBSession>("SELECT * FROM sessions WHERE id = $1")
-        .bind(header.token().trim())
-        .fetch_one(db_pool)
-        .await;
+    let Some((id, secret)) = header.token().trim().split_once('.') else {
+        return Err(sqlx::Error::RowNotFound);
+    };
+    let Ok(id) = id.parse::<u32>() else {
+        return Err(sqlx::Error::RowNotFound);
+    };
+
+    let now = Utc::now().timestamp();
+
+    let res =
+        sqlx::query_as::<_, DBSession>("SELECT * FROM sessions WHERE id = $1 AND expires > $2")
+            .bind(id)
+            .bind(now)
+            .fetch_one(db_pool)
+            .await;
 
     tracing::debug!("{:#?}", &res);
 
-    res
+    let mut session = res?;
+
+    if !bool::from(session.token_hash.ct_eq(&hash_session_secret(secret))) {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    if now - session.last_seen >= SESSION_RENEW_INTERVAL {
+        session.last_seen = now;
+        session.expires = (now + SESSION_IDLE_TIMEOUT).min(session.created + SESSION_MAX_AGE);
+
+        sqlx::query("UPDATE sessions SET last_seen = $1, expires = $2 WHERE id = $3")
+            .bind(session.last_seen)
+            .bind(session.expires)
+            .bind(session.id)
+            .execute(db_pool)
+            .await?;
+    }
+
+    Ok(session)
+}
+
+/// Delete every expired session, returning how many were deleted.
+///
+/// # Errors
+/// See [`sqlx::error::Error`]
+pub async fn delete_expired_sessions(db_pool: &Pool<Sqlite>) -> Result<u64, sqlx::error::Error> {
+    Ok(sqlx::query("DELETE FROM sessions WHERE expires <= $1")
+        .bind(Utc::now().timestamp())
+        .execute(db_pool)
+        .await?
+        .rows_affected())
 }
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use common::ModerationAction;
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+use tokio::sync::Mutex;
+
+use crate::auth::format_remaining;
+use crate::db::log_event;
+use server::{DBModerationEvent, SYSTEM_ACTOR};
+
+/// How failed logins of one username, or from one IP address, are slowed down
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Failures allowed before any waiting
+    pub free_attempts: u32,
+    /// Seconds to wait after the first failure past `free_attempts`, doubled by every failure after it
+    pub base_delay: i64,
+    /// Longest wait between attempts, in seconds
+    pub max_delay: i64,
+    /// Failures after which nothing is tried for `lockout_duration` seconds. 0 never locks out.
+    pub lockout_attempts: u32,
+    pub lockout_duration: i64,
+}
+
+/// Brute-force protection for `/api/login`, and for the routes that ask a logged in user for their password again.
+///
+/// Failures are counted for the username tried and for the IP address it came from,
+/// so that guessing many passwords for one account, or one password for many accounts, are both slowed down.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutRules {
+    /// Seconds after which a failure is forgotten
+    pub window: i64,
+    /// Take the client's IP address from the last entry of `X-Forwarded-For`.
+    /// Only turn on behind a reverse proxy that sets it, otherwise anyone can pick their own IP address.
+    pub trust_forwarded_for: bool,
+    pub username: Limits,
+    /// Looser than `username`, since many people can share an IP address
+    pub ip: Limits,
+
+    /// Held while an attempt is checked and recorded, so that parallel attempts cannot all get past the check
+    #[serde(skip)]
+    guard: Arc<Mutex<()>>,
+}
+
+impl Default for LockoutRules {
+    fn default() -> Self {
+        Self {
+            window: 60 * 60,
+            trust_forwarded_for: false,
+            username: Limits {
+                free_attempts: 5,
+                base_delay: 2,
+                max_delay: 5 * 60,
+                lockout_attempts: 10,
+                lockout_duration: 15 * 60,
+            },
+            ip: Limits {
+                free_attempts: 20,
+                base_delay: 1,
+                max_delay: 60,
+                lockout_attempts: 100,
+                lockout_duration: 60 * 60,
+            },
+            guard: Arc::default(),
+        }
+    }
+}
+
+/// A login refused before the password was checked, because of earlier failures.
+///
+/// Responds with `429 Too Many Requests` and a `Retry-After` header.
+/// It does not say whether the username or the IP address is the reason, or whether the username exists.
+#[derive(Debug, Clone)]
+pub struct Lockout {
+    /// Seconds until another attempt is allowed
+    pub retry_after: i64,
+}
+
+impl fmt::Display for Lockout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many failed logins, try again in {}",
+            format_remaining(self.retry_after)
+        )
+    }
+}
+
+impl IntoResponse for Lockout {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, self.retry_after.to_string())],
+            self.to_string(),
+        )
+            .into_response()
+    }
+}
+
+/// A password check that is counted as a failure until it succeeds, see [`LockoutRules::attempt`]
+#[derive(Debug)]
+pub struct Attempt {
+    /// Its row in the `login_failures` table
+    id: i64,
+    username: String,
+    ip: String,
+}
+
+/// What failed logins are counted by, as columns of the `login_failures` table
+#[derive(Debug, Clone, Copy)]
+enum Key {
+    Username,
+    Ip,
+}
+
+impl Key {
+    fn column(self) -> &'static str {
+        match self {
+            Key::Username => "username",
+            Key::Ip => "ip",
+        }
+    }
+
+    /// The `target_kind` of its audit log entries
+    fn target_kind(self) -> &'static str {
+        match self {
+            Key::Username => "user",
+            Key::Ip => "ip",
+        }
+    }
+}
+
+impl LockoutRules {
+    /// The address that the request came from, see [`LockoutRules::trust_forwarded_for`]
+    #[must_use]
+    pub fn client_ip(&self, addr: SocketAddr, headers: &HeaderMap) -> String {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty());
+
+        match forwarded {
+            Some(ip) if self.trust_forwarded_for => ip.to_string(),
+            _ => addr.ip().to_string(),
+        }
+    }
+
+    /// Start an attempt at the password of `username` from `ip`, if they can be tried yet.
+    ///
+    /// The attempt is recorded as a failure before the password is checked, so that parallel attempts count against each other,
+    /// and is only forgotten by [`LockoutRules::record_success`].
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn attempt(
+        &self,
+        username: &str,
+        ip: &str,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Result<Attempt, Lockout>, sqlx::error::Error> {
+        let _guard = self.guard.lock().await;
+
+        let by_username = self
+            .wait(Key::Username, username, self.username, db_pool)
+            .await?;
+        let by_ip = self.wait(Key::Ip, ip, self.ip, db_pool).await?;
+
+        if let Some(retry_after) = by_username.max(by_ip) {
+            return Ok(Err(Lockout { retry_after }));
+        }
+
+        let now = Utc::now().timestamp();
+
+        sqlx::query("DELETE FROM login_failures WHERE created <= $1")
+            .bind(now - self.window)
+            .execute(db_pool)
+            .await?;
+
+        let id =
+            sqlx::query("INSERT INTO login_failures (created, username, ip) VALUES ($1, $2, $3)")
+                .bind(now)
+                .bind(username)
+                .bind(ip)
+                .execute(db_pool)
+                .await?
+                .last_insert_rowid();
+
+        Ok(Ok(Attempt {
+            id,
+            username: username.to_string(),
+            ip: ip.to_string(),
+        }))
+    }
+
+    /// Finish an `attempt` whose password was wrong, or whose username does not exist.
+    ///
+    /// Locking its username or IP address out is added to the audit log.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn record_failure(
+        &self,
+        attempt: Attempt,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<(), sqlx::error::Error> {
+        let now = Utc::now().timestamp();
+
+        for (key, value, limits) in [
+            (Key::Username, &attempt.username, self.username),
+            (Key::Ip, &attempt.ip, self.ip),
+        ] {
+            let (failures, _) = self.failures(key, value, db_pool).await?;
+
+            // only the failure that starts the lockout is logged, not every one during it
+            if limits.lockout_attempts == 0 || failures != i64::from(limits.lockout_attempts) {
+                continue;
+            }
+
+            tracing::warn!(
+                "{} {value:?} locked out of logging in for {}s after {failures} failures",
+                key.column(),
+                limits.lockout_duration
+            );
+
+            let mut event = DBModerationEvent::new(
+                SYSTEM_ACTOR,
+                ModerationAction::LoginLockedOut,
+                key.target_kind(),
+                value,
+            );
+            event.reason = Some(format!("{failures} failed logins"));
+            event.details = Some(format!("until {}", now + limits.lockout_duration));
+            log_event(&event, db_pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Finish an `attempt` whose password was right, forgetting it and the earlier failures of its username from its IP address.
+    ///
+    /// Failures from other addresses are kept, so that logging in does not help someone else guessing.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn record_success(
+        &self,
+        attempt: Attempt,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<(), sqlx::error::Error> {
+        sqlx::query("DELETE FROM login_failures WHERE id = $1 OR (username = $2 AND ip = $3)")
+            .bind(attempt.id)
+            .bind(&attempt.username)
+            .bind(&attempt.ip)
+            .execute(db_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// How many failures `value` has within the `window`, and when the last one was
+    async fn failures(
+        &self,
+        key: Key,
+        value: &str,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<(i64, Option<i64>), sqlx::error::Error> {
+        sqlx::query_as(&format!(
+            "SELECT COUNT(*), MAX(created) FROM login_failures WHERE {} = $1 AND created > $2",
+            key.column()
+        ))
+        .bind(value)
+        .bind(Utc::now().timestamp() - self.window)
+        .fetch_one(db_pool)
+        .await
+    }
+
+    /// Seconds until `value` can be tried again, if it has to wait
+    async fn wait(
+        &self,
+        key: Key,
+        value: &str,
+        limits: Limits,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Option<i64>, sqlx::error::Error> {
+        let (failures, last) = self.failures(key, value, db_pool).await?;
+        let Some(last) = last else {
+            return Ok(None);
+        };
+
+        let delay = if limits.lockout_attempts > 0 && failures >= i64::from(limits.lockout_attempts)
+        {
+            limits.lockout_duration
+        } else if failures >= i64::from(limits.free_attempts) {
+            // doubles with every failure, until it reaches `max_delay`
+            let doublings = (failures - i64::from(limits.free_attempts)).min(32);
+            limits
+                .base_delay
+                .saturating_mul(1 << doublings)
+                .min(limits.max_delay)
+        } else {
+            return Ok(None);
+        };
+
+        let retry_after = last + delay - Utc::now().timestamp();
+
+        Ok((retry_after > 0).then_some(retry_after))
+    }
+}
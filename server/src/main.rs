@@ -3,11 +3,12 @@ use axum::http::{Request, StatusCode};
 use axum::response::Html;
 use axum::{
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 
 use clap::Parser;
+use common::Role;
 
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::ConnectOptions;
@@ -16,16 +17,39 @@ use std::env;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::fs;
 use tower::{ServiceBuilder, ServiceExt};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
-use crate::routes::{add_comment, create_account, get_posts, login, submit_post, validate_session};
+use crate::advice::hub::AdviceHub;
+use crate::advice::pool::AdvicePool;
+use crate::advice::{build_provider, ProviderKind, ProviderOpts};
+use crate::config::Config;
+use crate::db::create_tables;
+use crate::jobs::{JobLimits, JobQueue};
+use crate::routes::{
+    account, add_comment, admin, create_account, get_posts, login, preferences, regenerate_advice,
+    reports, risk_reviews, sessions as session_routes, stream_advice, submit_post,
+    validate_session,
+};
+use crate::state::AppState;
 
+pub mod advice;
+mod auth;
+mod config;
 pub mod db;
+mod jobs;
+mod lockout;
+mod moderation;
+mod risk;
 mod routes;
+mod sessions;
+mod spam;
+mod state;
 
 #[allow(clippy::unused_async)]
 #[derive(Parser, Debug)]
@@ -42,6 +66,66 @@ struct Opt {
     /// set the directory where static files are to be found
     #[clap(long = "static-dir", default_value = "../dist")]
     static_dir: String,
+
+    /// set the config file, defaults to `config.toml` in the server directory
+    #[clap(short = 'c', long = "config")]
+    config: Option<PathBuf>,
+
+    /// set where AI advice comes from
+    #[clap(long = "advice-provider", value_enum, default_value = "g4f")]
+    advice_provider: ProviderKind,
+
+    /// set the providers tried, in order, when a response is rejected or fails
+    #[clap(long = "advice-fallback", value_enum, value_delimiter = ',')]
+    advice_fallback: Vec<ProviderKind>,
+
+    /// set the base url of the OpenAI-compatible API used by the `openai` provider
+    #[clap(long = "openai-url", default_value = "http://localhost:8000/v1")]
+    openai_url: String,
+
+    /// set the model requested from the `openai` provider
+    #[clap(long = "openai-model", default_value = "gpt-3.5-turbo")]
+    openai_model: String,
+
+    /// set the API key sent to the `openai` provider, falls back to the `OPENAI_API_KEY` env var
+    #[clap(long = "openai-key")]
+    openai_key: Option<String>,
+
+    /// set how many AI calls run at the same time, each on its own thread
+    #[clap(long = "ai-workers", default_value = "2")]
+    ai_workers: usize,
+
+    /// set how many unfinished AI jobs are allowed before new posts are refused
+    #[clap(long = "ai-queue-depth", default_value = "32")]
+    ai_queue_depth: u32,
+
+    /// set how many seconds a single AI call may take
+    #[clap(long = "ai-timeout", default_value = "60")]
+    ai_timeout: u64,
+
+    /// set how many times an AI job is tried before it is marked as failed
+    #[clap(long = "ai-max-attempts", default_value = "4")]
+    ai_max_attempts: u32,
+
+    /// set how many follow-up questions a post author can ask the AI
+    #[clap(long = "ai-max-followups", default_value = "5")]
+    ai_max_followups: u32,
+
+    /// set how many times a post author can regenerate the AI's advice
+    #[clap(long = "ai-max-regenerations", default_value = "3")]
+    ai_max_regenerations: u32,
+
+    /// set how many of the most recent comments the AI sees when answering a follow-up
+    #[clap(long = "ai-max-thread", default_value = "20")]
+    ai_max_thread: usize,
+
+    /// set the file holding the key that session tokens are hashed with, made if missing, defaults to `session.key` in the server directory
+    #[clap(long = "session-key")]
+    session_key: Option<PathBuf>,
+
+    /// make an existing account an admin, then exit without starting the server
+    #[clap(long = "make-admin", value_name = "USERNAME")]
+    make_admin: Option<String>,
 }
 
 #[tokio::main]
@@ -56,12 +140,32 @@ async fn main() -> anyhow::Result<()> {
     // enable console logging
     tracing_subscriber::fmt::init();
 
-    let db_path: &str = if std::env::current_dir()?.ends_with("server") {
+    let in_server_dir = std::env::current_dir()?.ends_with("server");
+
+    let db_path: &str = if in_server_dir {
         "sqlite://all.db"
     } else {
         "sqlite://server/all.db"
     };
 
+    let config_path = opt.config.clone().unwrap_or_else(|| {
+        if in_server_dir {
+            PathBuf::from("config.toml")
+        } else {
+            PathBuf::from("server/config.toml")
+        }
+    });
+    let config = Config::load(&config_path)?;
+
+    let session_key_path = opt.session_key.clone().unwrap_or_else(|| {
+        if in_server_dir {
+            PathBuf::from("session.key")
+        } else {
+            PathBuf::from("server/session.key")
+        }
+    });
+    server::set_session_key(sessions::load_key(&session_key_path)?);
+
     {
         let mut db_connection = SqliteConnectOptions::new()
             .filename(db_path.split("//").nth(1).unwrap())
@@ -69,25 +173,27 @@ async fn main() -> anyhow::Result<()> {
             .connect()
             .await?;
 
-        sqlx::query("CREATE TABLE IF NOT EXISTS posts (id INTEGER PRIMARY KEY, username TEXT NOT NULL, content TEXT NOT NULL, created INTEGER NOT NULL)")
-            .execute(&mut db_connection)
-            .await?;
+        create_tables(&mut db_connection).await?;
 
-        sqlx::query("CREATE TABLE IF NOT EXISTS comments (id INTEGER PRIMARY KEY, post_id INTEGER NOT NULL, username TEXT NOT NULL, content TEXT NOT NULL, created INTEGER NOT NULL)")
-            .execute(&mut db_connection)
-            .await?;
+        tracing::debug!("db,tables exists");
 
-        sqlx::query("CREATE TABLE IF NOT EXISTS users (username TEXT PRIMARY KEY, hashed_password TEXT NOT NULL, created INTEGER NOT NULL)")
-            .execute(&mut db_connection)
-            .await?;
+        if let Some(username) = &opt.make_admin {
+            let updated = sqlx::query("UPDATE users SET role = $1 WHERE username = $2")
+                .bind(Role::Admin.as_str())
+                .bind(username)
+                .execute(&mut db_connection)
+                .await?
+                .rows_affected();
 
-        sqlx::query(
-            "CREATE TABLE IF NOT EXISTS sessions (username TEXT PRIMARY KEY, id INTEGER NOT NULL)",
-        )
-        .execute(&mut db_connection)
-        .await?;
+            anyhow::ensure!(
+                updated > 0,
+                "no account named {username:?}, create it first"
+            );
 
-        tracing::debug!("db,tables exists");
+            tracing::info!("{username:?} is now an admin");
+
+            return Ok(());
+        }
     }
 
     let db_pool = SqlitePoolOptions::new()
@@ -97,16 +203,73 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::debug!("db pool ready");
 
+    let provider_opts = ProviderOpts {
+        openai_url: opt.openai_url.clone(),
+        openai_model: opt.openai_model.clone(),
+        openai_key: opt
+            .openai_key
+            .clone()
+            .or_else(|| env::var("OPENAI_API_KEY").ok()),
+        timeout: Duration::from_secs(opt.ai_timeout),
+    };
+    let providers = std::iter::once(opt.advice_provider)
+        .chain(opt.advice_fallback.iter().copied())
+        .map(|kind| build_provider(kind, &provider_opts))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let pool = AdvicePool::new(opt.ai_workers, Duration::from_secs(opt.ai_timeout));
+    let hub = AdviceHub::default();
+    let moderation = Arc::new(config.moderation);
+    moderation.reload_words(&db_pool).await?;
+
+    let jobs = JobQueue::new(
+        db_pool.clone(),
+        providers,
+        pool,
+        hub.clone(),
+        config.prompts,
+        config.ai_output,
+        moderation.clone(),
+        JobLimits {
+            max_attempts: opt.ai_max_attempts,
+            max_depth: opt.ai_queue_depth,
+            max_followups: opt.ai_max_followups,
+            max_regenerations: opt.ai_max_regenerations,
+            max_thread: opt.ai_max_thread,
+        },
+    );
+    jobs.recover().await?;
+    jobs.spawn_workers(opt.ai_workers);
+
+    sessions::spawn_sweeper(db_pool.clone());
+
+    let state = AppState {
+        db_pool,
+        jobs,
+        hub,
+        risk: Arc::new(config.risk),
+        moderation,
+        reports: Arc::new(config.reports),
+        spam: Arc::new(config.spam),
+        lockout: Arc::new(config.lockout),
+    };
+
     #[rustfmt::skip]
     let app = Router::new()
         // does not require session id, pure GET
         .route("/api/get_posts", get(get_posts::route))
+
+        // does not require session id, server-sent events of `AdviceChunk`s, then the finished `Comment`
+        .route("/api/posts/:id/advice/stream", get(stream_advice::route))
         
         // requires valid Authentication<Bearer> = session_id and String body
         .route("/api/submit_post", post(submit_post::route))
         
         // requires valid Authentication<Bearer> = session_id and Json<InputComment>
         .route("/api/add_comment", post(add_comment::route))
+
+        // requires valid Authentication<Bearer> = session_id of the post's author
+        .route("/api/posts/:id/regenerate_advice", post(regenerate_advice::route))
         
         // does not require session id, requires valid Json<User>
         .route("/api/create_account", post(create_account::route))
@@ -116,7 +279,44 @@ async fn main() -> anyhow::Result<()> {
 
         // requires valid Authentication<Bearer> = session_id
         .route("/api/validate_session", get(validate_session::route))
-        .with_state(db_pool)
+        .route("/api/preferences", get(preferences::get).put(preferences::set))
+        .route("/api/logout", post(session_routes::logout))
+        .route("/api/sessions", get(session_routes::list).delete(session_routes::revoke_all))
+        .route("/api/sessions/:id", delete(session_routes::revoke))
+
+        // requires valid Authentication<Bearer> = session_id and the account's current password in the Json body
+        .route("/api/account/password", post(account::change_password))
+        .route("/api/account", delete(account::delete))
+
+        // requires valid Authentication<Bearer> = session_id of a moderator
+        .route("/api/risk_reviews", get(risk_reviews::list))
+        .route("/api/risk_reviews/:id/resolve", post(risk_reviews::resolve))
+
+        // POST requires valid Authentication<Bearer> = session_id and Json<InputReport>,
+        // GET requires valid Authentication<Bearer> = session_id of a moderator
+        .route("/api/reports", post(reports::create).get(reports::list))
+
+        // requires valid Authentication<Bearer> = session_id of a moderator
+        .route("/api/reports/:id/resolve", post(reports::resolve))
+        .route("/api/reports/:id/dismiss", post(reports::dismiss))
+
+        // requires valid Authentication<Bearer> = session_id of a moderator, or an admin to set roles and edit word lists
+        .nest("/api/admin", Router::new()
+            .route("/posts/:id", delete(admin::delete_post))
+            .route("/comments/:id", delete(admin::delete_comment))
+            .route("/users/:username/ban", post(admin::ban))
+            .route("/users/:username/unban", post(admin::unban))
+            .route("/users/:username/mute", post(admin::mute))
+            .route("/users/:username/unmute", post(admin::unmute))
+            .route("/users/:username/shadow_ban", post(admin::shadow_ban))
+            .route("/users/:username/unshadow_ban", post(admin::unshadow_ban))
+            .route("/users/:username/role", put(admin::set_role))
+            .route("/word_lists", get(admin::list_words).post(admin::add_word))
+            .route("/word_lists/:id", delete(admin::remove_word))
+            .route("/word_lists/reload", post(admin::reload_words))
+            .route("/events", get(admin::list_events))
+        )
+        .with_state(state)
         .fallback_service(get(|req: Request<Body>| async move {
             let res = ServeDir::new(&opt.static_dir).oneshot(req).await.unwrap(); // serve dir is infallible
             let status = res.status();
@@ -140,14 +340,12 @@ async fn main() -> anyhow::Result<()> {
         opt.port,
     ));
 
-    pyo3::prepare_freethreaded_python();
-    tracing::debug!("python ready");
-
     tracing::info!("listening on http://{sock_addr}");
     tracing::info!("in directory: {:#?}", env::current_dir()?);
 
     axum::Server::bind(&sock_addr)
-        .serve(app.into_make_service())
+        // the client's address is needed to slow down password guessing, see `lockout.rs`
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
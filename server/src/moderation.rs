@@ -0,0 +1,348 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use common::{ModerationAction, WordList};
+use rustrict::{Censor, CensorStr, Type};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use server::{DBModerationEvent, DBWord, SYSTEM_ACTOR};
+
+mod cantonese;
+
+/// What is being moderated. Each kind has its own [`Policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Post,
+    Comment,
+    Username,
+    AiOutput,
+}
+
+impl fmt::Display for ContentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContentKind::Post => "post",
+            ContentKind::Comment => "comment",
+            ContentKind::Username => "username",
+            ContentKind::AiOutput => "ai output",
+        })
+    }
+}
+
+/// The lowest severity of a category that is blocked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Threshold {
+    /// Never blocked
+    Off,
+    Mild,
+    Moderate,
+    Severe,
+}
+
+impl Threshold {
+    fn severities(self) -> Type {
+        match self {
+            Threshold::Off => Type::NONE,
+            Threshold::Mild => Type::MILD_OR_HIGHER,
+            Threshold::Moderate => Type::MODERATE_OR_HIGHER,
+            Threshold::Severe => Type::SEVERE,
+        }
+    }
+}
+
+/// A category of inappropriate content, as detected by `rustrict`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Profane,
+    Offensive,
+    Sexual,
+    Mean,
+    Evasive,
+    Spam,
+}
+
+impl Category {
+    const ALL: [Category; 6] = [
+        Category::Profane,
+        Category::Offensive,
+        Category::Sexual,
+        Category::Mean,
+        Category::Evasive,
+        Category::Spam,
+    ];
+
+    fn typ(self) -> Type {
+        match self {
+            Category::Profane => Type::PROFANE,
+            Category::Offensive => Type::OFFENSIVE,
+            Category::Sexual => Type::SEXUAL,
+            Category::Mean => Type::MEAN,
+            Category::Evasive => Type::EVASIVE,
+            Category::Spam => Type::SPAM,
+        }
+    }
+}
+
+/// How severe each category has to be before content is blocked
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub profane: Threshold,
+    pub offensive: Threshold,
+    pub sexual: Threshold,
+    pub mean: Threshold,
+    pub evasive: Threshold,
+    pub spam: Threshold,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            profane: Threshold::Off,
+            offensive: Threshold::Mild,
+            sexual: Threshold::Moderate,
+            mean: Threshold::Off,
+            evasive: Threshold::Off,
+            spam: Threshold::Off,
+        }
+    }
+}
+
+impl Policy {
+    fn threshold(&self, category: Category) -> Threshold {
+        match category {
+            Category::Profane => self.profane,
+            Category::Offensive => self.offensive,
+            Category::Sexual => self.sexual,
+            Category::Mean => self.mean,
+            Category::Evasive => self.evasive,
+            Category::Spam => self.spam,
+        }
+    }
+}
+
+/// The result of [`Moderation::check`]
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub kind: ContentKind,
+    /// What `rustrict` and the Cantonese filter found, whether or not it was blocked
+    pub analysis: Type,
+    /// Every category that went over the policy's threshold
+    pub violations: Vec<Category>,
+    /// Every word from the custom block list that the content contains
+    pub blocked_words: Vec<String>,
+    /// Every term from the Cantonese filter's lexicon that matched
+    pub cantonese_terms: Vec<&'static str>,
+}
+
+impl Verdict {
+    #[must_use]
+    pub fn is_allowed(&self) -> bool {
+        self.violations.is_empty() && self.blocked_words.is_empty()
+    }
+
+    /// The audit log entry for `username`'s `content` being rejected by this verdict
+    #[must_use]
+    pub fn rejection(&self, username: &str, content: &str) -> DBModerationEvent {
+        let mut event = DBModerationEvent::new(
+            SYSTEM_ACTOR,
+            ModerationAction::FilterRejected,
+            "user",
+            username,
+        );
+        event.reason = Some(self.to_string());
+        event.details = Some(content.to_string());
+        event
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_allowed() {
+            write!(f, "{} allowed", self.kind)
+        } else {
+            write!(
+                f,
+                "{} blocked for {:?} ({:?}), blocked words {:?}, cantonese terms {:?}",
+                self.kind, self.violations, self.analysis, self.blocked_words, self.cantonese_terms
+            )
+        }
+    }
+}
+
+/// The custom word lists from the `word_lists` table, lowercased
+#[derive(Debug, Default)]
+struct WordLists {
+    block: Vec<String>,
+    allow: Vec<String>,
+}
+
+/// The moderation policy for every [`ContentKind`].
+///
+/// Every route checks content through here, instead of running its own filter.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Moderation {
+    pub post: Policy,
+    pub comment: Policy,
+    pub username: Policy,
+    pub ai_output: Policy,
+
+    /// Applied on top of `rustrict` for every kind of content, see [`Moderation::reload_words`]
+    #[serde(skip)]
+    words: Arc<RwLock<WordLists>>,
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        let comment = Policy {
+            mean: Threshold::Mild,
+            ..Policy::default()
+        };
+
+        Self {
+            post: Policy::default(),
+            username: Policy {
+                profane: Threshold::Mild,
+                sexual: Threshold::Mild,
+                ..comment.clone()
+            },
+            ai_output: comment.clone(),
+            comment,
+            words: Arc::default(),
+        }
+    }
+}
+
+impl Moderation {
+    #[must_use]
+    pub fn policy(&self, kind: ContentKind) -> &Policy {
+        match kind {
+            ContentKind::Post => &self.post,
+            ContentKind::Comment => &self.comment,
+            ContentKind::Username => &self.username,
+            ContentKind::AiOutput => &self.ai_output,
+        }
+    }
+
+    /// Read the custom word lists from the database again, so that changes apply without a restart.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn reload_words(&self, db_pool: &Pool<Sqlite>) -> Result<(), sqlx::error::Error> {
+        let words: Vec<DBWord> = sqlx::query_as::<_, DBWord>("SELECT * FROM word_lists")
+            .fetch_all(db_pool)
+            .await?;
+
+        let mut lists = WordLists::default();
+        for word in words {
+            match WordList::from_db(&word.list) {
+                WordList::Block => lists.block.push(word.word.to_lowercase()),
+                WordList::Allow => lists.allow.push(word.word.to_lowercase()),
+            }
+        }
+
+        tracing::info!(
+            "loaded {} blocked and {} allowed words",
+            lists.block.len(),
+            lists.allow.len()
+        );
+
+        *self.words.write().unwrap() = lists;
+
+        Ok(())
+    }
+
+    /// Check `text` against the policy for `kind`.
+    ///
+    /// Words on the allow list are removed before `rustrict` and the Cantonese filter see the text,
+    /// and words on the block list are always blocked. The Cantonese filter's findings are
+    /// judged by the same thresholds as `rustrict`'s.
+    #[must_use]
+    pub fn check(&self, kind: ContentKind, text: &str) -> Verdict {
+        let words = self.words.read().unwrap();
+
+        let mut lowercase = text.to_lowercase();
+        let blocked_words: Vec<String> = words
+            .block
+            .iter()
+            .filter(|word| contains_word(&lowercase, word))
+            .cloned()
+            .collect();
+
+        let mut masked = false;
+        for word in &words.allow {
+            if contains_word(&lowercase, word) {
+                lowercase = replace_word(&lowercase, word, " ");
+                masked = true;
+            }
+        }
+
+        // only lowercase the text when it has to be, so `rustrict` still sees the original casing
+        let text = if masked { &lowercase } else { text };
+
+        let (cantonese, cantonese_terms) = cantonese::analyze(text);
+        let analysis = Censor::from_str(text).analyze() | cantonese;
+        let policy = self.policy(kind);
+
+        let violations = Category::ALL
+            .into_iter()
+            .filter(|&category| {
+                analysis.is(category.typ() & policy.threshold(category).severities())
+            })
+            .collect();
+
+        Verdict {
+            kind,
+            analysis,
+            violations,
+            blocked_words,
+            cantonese_terms,
+        }
+    }
+}
+
+/// `text` with inappropriate words masked with `*`, for viewers who have not opted out of censoring.
+#[must_use]
+pub fn censor(text: &str) -> String {
+    text.censor()
+}
+
+/// The byte ranges where `word` appears in `text` on its own, not inside a longer latin word.
+///
+/// Scripts without spaces between words, like Chinese, always match.
+fn word_matches<'a>(text: &'a str, word: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    text.match_indices(word)
+        .map(move |(start, _)| (start, start + word.len()))
+        .filter(move |&(start, end)| {
+            let before = text[..start].chars().next_back();
+            let after = text[end..].chars().next();
+
+            let starts_inside = word.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && before.is_some_and(|c| c.is_ascii_alphanumeric());
+            let ends_inside = word.ends_with(|c: char| c.is_ascii_alphanumeric())
+                && after.is_some_and(|c| c.is_ascii_alphanumeric());
+
+            !starts_inside && !ends_inside
+        })
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    word_matches(text, word).next().is_some()
+}
+
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, end) in word_matches(text, word) {
+        replaced.push_str(&text[last..start]);
+        replaced.push_str(with);
+        last = end;
+    }
+    replaced.push_str(&text[last..]);
+
+    replaced
+}
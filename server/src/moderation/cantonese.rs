@@ -0,0 +1,330 @@
+//! A supplementary filter for Cantonese, Chinese and romanised Cantonese profanity, which `rustrict` mostly misses.
+//!
+//! Text is normalised before matching: Simplified characters and variant characters are mapped to
+//! the Traditional ones in the lexicon, and everything between Chinese characters, like inserted spaces,
+//! symbols or latin letters, is dropped. Terms are only matched within a sentence, so the text is split
+//! on sentence punctuation and newlines first. Multi-character terms also match common homophones,
+//! like 吊 or 刁 for 屌.
+
+use rustrict::Type;
+
+use super::Category;
+
+/// Severity of a term in each category, `[profane, offensive, sexual, mean]`.
+/// `0` is none, then mild, moderate and severe.
+type Levels = [u8; 4];
+
+/// Single characters that are only ever used as profanity. Matched without homophones,
+/// because their homophones are everyday characters.
+const HAN_CHARS: &[(&str, Levels)] = &[
+    ("屌", [2, 0, 2, 0]),
+    ("閪", [3, 1, 3, 0]),
+    ("肏", [2, 0, 2, 0]),
+];
+
+/// Terms of more than one character, in Traditional Chinese. Matched after homophones are mapped.
+const HAN_TERMS: &[(&str, Levels)] = &[
+    ("屌你", [2, 0, 2, 3]),
+    ("你老母", [2, 2, 2, 2]),
+    ("你老味", [1, 0, 0, 2]),
+    ("仆街", [2, 0, 0, 2]),
+    ("戇鳩", [2, 0, 0, 2]),
+    ("鳩噏", [1, 0, 0, 0]),
+    ("柒頭", [2, 0, 1, 2]),
+    ("含撚", [3, 0, 3, 2]),
+    ("撚樣", [2, 0, 0, 1]),
+    ("食屎", [2, 0, 0, 2]),
+    ("頂你個肺", [1, 0, 0, 1]),
+    ("他媽的", [2, 0, 0, 0]),
+    ("操你媽", [2, 2, 2, 2]),
+    ("草泥馬", [2, 0, 0, 2]),
+    ("傻閪", [3, 0, 2, 3]),
+    ("傻逼", [3, 0, 2, 3]),
+    ("冚家鏟", [0, 3, 0, 3]),
+    ("死全家", [0, 3, 0, 3]),
+    ("支那", [3, 3, 0, 0]),
+    ("黑鬼", [3, 3, 0, 0]),
+    ("躝屍", [0, 0, 0, 2]),
+    ("賤人", [0, 0, 0, 2]),
+    ("八婆", [0, 0, 0, 2]),
+    ("痴線", [0, 0, 0, 1]),
+    ("白痴", [0, 0, 0, 1]),
+    ("蠢材", [0, 0, 0, 1]),
+    ("廢柴", [0, 0, 0, 1]),
+    ("收皮", [0, 0, 0, 1]),
+];
+
+/// Ordinary phrases that contain a term, masked before terms are matched
+const HARMLESS_PHRASES: &[&str] = &["老母親"];
+
+/// Terms mixing Chinese characters with latin letters or digits, matched with the latin part lowercased.
+/// The latin part must be a whole word, so that `含l` does not match `包含 list`.
+const MIXED_TERMS: &[(&str, Levels)] = &[
+    ("仆gai", [2, 0, 0, 2]),
+    ("pk街", [2, 0, 0, 2]),
+    ("含l", [3, 0, 3, 2]),
+    ("戇9", [2, 0, 0, 2]),
+];
+
+/// Romanised Cantonese, without spaces. Matched against whole words, and against two or three words joined together.
+const ROMANISED_TERMS: &[(&str, Levels)] = &[
+    ("diu", [2, 0, 2, 0]),
+    ("dllm", [2, 2, 2, 2]),
+    ("diulei", [2, 0, 2, 3]),
+    ("diuneilomo", [2, 2, 2, 2]),
+    ("nlm", [2, 2, 2, 2]),
+    ("on9", [2, 0, 0, 2]),
+    ("pukgai", [2, 0, 0, 2]),
+    ("pokgai", [2, 0, 0, 2]),
+    ("hamgachan", [0, 3, 0, 3]),
+    ("hamgaachaan", [0, 3, 0, 3]),
+    ("hamlun", [3, 0, 3, 2]),
+    ("lunyeung", [2, 0, 0, 1]),
+    ("sohai", [3, 0, 2, 3]),
+    ("sorhai", [3, 0, 2, 3]),
+    ("chisin", [0, 0, 0, 1]),
+];
+
+/// Simplified and variant characters, mapped to the Traditional characters used in the lexicon
+const VARIANTS: &[(char, char)] = &[
+    ('鸠', '鳩'),
+    ('𨳊', '鳩'),
+    ('𨳒', '屌'),
+    ('𨶙', '閪'),
+    ('屄', '閪'),
+    ('𡳞', '撚'),
+    ('铲', '鏟'),
+    ('产', '產'),
+    ('戆', '戇'),
+    ('线', '線'),
+    ('贱', '賤'),
+    ('顶', '頂'),
+    ('个', '個'),
+    ('头', '頭'),
+    ('妈', '媽'),
+    ('马', '馬'),
+    ('废', '廢'),
+    ('尸', '屍'),
+    ('样', '樣'),
+    ('黐', '痴'),
+    ('癡', '痴'),
+];
+
+/// Cantonese homophones that are used to dodge filters, mapped to the character they stand for.
+/// Everyday characters that start ordinary phrases, like 丟 (`丟你` is "leave you") or 七 (`七頭牛` is "seven cows"), are left out.
+const HOMOPHONES: &[(char, char)] = &[
+    ('吊', '屌'),
+    ('刁', '屌'),
+    ('西', '閪'),
+    ('九', '鳩'),
+    ('扑', '仆'),
+    ('僕', '仆'),
+    ('卜', '仆'),
+    ('咸', '冚'),
+    ('產', '鏟'),
+];
+
+/// Sentence punctuation and newlines, after [`fold`]. Terms are not matched across them.
+fn is_sentence_break(c: char) -> bool {
+    matches!(
+        c,
+        ',' | '!' | '?' | ';' | ':' | '。' | '、' | '…' | '\n' | '\r'
+    )
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c as u32, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3134F)
+}
+
+fn map(c: char, table: &[(char, char)]) -> char {
+    table
+        .iter()
+        .find(|(from, _)| *from == c)
+        .map_or(c, |(_, to)| *to)
+}
+
+/// Full-width latin letters and digits to ASCII, lowercased
+fn fold(c: char) -> char {
+    let c = match c as u32 {
+        0xFF01..=0xFF5E => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    };
+    c.to_ascii_lowercase()
+}
+
+fn levels_to_type(levels: Levels) -> Type {
+    const CATEGORIES: [Category; 4] = [
+        Category::Profane,
+        Category::Offensive,
+        Category::Sexual,
+        Category::Mean,
+    ];
+
+    CATEGORIES
+        .into_iter()
+        .zip(levels)
+        .fold(Type::NONE, |typ, (category, level)| {
+            let severity = match level {
+                0 => return typ,
+                1 => Type::MILD,
+                2 => Type::MODERATE,
+                _ => Type::SEVERE,
+            };
+            typ | (category.typ() & severity)
+        })
+}
+
+/// Words made of latin letters and digits. Runs of single letters, like `d l l m`, are joined into one word.
+fn romanised_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut joining = false;
+
+    for word in text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let single = word.len() == 1;
+
+        match words.last_mut() {
+            Some(last) if single && joining => last.push_str(word),
+            _ => words.push(word.to_string()),
+        }
+
+        joining = single;
+    }
+
+    words
+}
+
+/// Whether `text` contains `term`, with its latin ends not joined to other latin letters or digits
+fn contains_word(text: &str, term: &str) -> bool {
+    let starts_latin = term.starts_with(|c: char| c.is_ascii_alphanumeric());
+    let ends_latin = term.ends_with(|c: char| c.is_ascii_alphanumeric());
+
+    text.match_indices(term).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + term.len()..].chars().next();
+
+        let joined_before = starts_latin && before.is_some_and(|c| c.is_ascii_alphanumeric());
+        let joined_after = ends_latin && after.is_some_and(|c| c.is_ascii_alphanumeric());
+
+        !joined_before && !joined_after
+    })
+}
+
+/// Every lexicon term in one sentence of folded text
+fn segment_matches(segment: &str) -> impl Iterator<Item = &'static (&'static str, Levels)> {
+    let mut han: String = segment.chars().filter(|&c| is_han(c)).collect();
+    for phrase in HARMLESS_PHRASES {
+        // masked with a character that is not han, so that terms cannot span the mask
+        han = han.replace(phrase, "|");
+    }
+
+    let sounds: String = han.chars().map(|c| map(c, HOMOPHONES)).collect();
+    let mixed: String = segment
+        .chars()
+        .filter(|&c| is_han(c) || c.is_ascii_alphanumeric())
+        .collect();
+
+    let words = romanised_words(segment);
+    let joined: Vec<String> = (1..=3)
+        .flat_map(|n| words.windows(n).map(<[String]>::concat))
+        .collect();
+
+    HAN_CHARS
+        .iter()
+        .filter(move |(term, _)| han.contains(term))
+        .chain(
+            HAN_TERMS
+                .iter()
+                .filter(move |(term, _)| sounds.contains(term)),
+        )
+        .chain(
+            MIXED_TERMS
+                .iter()
+                .filter(move |(term, _)| contains_word(&mixed, term)),
+        )
+        .chain(
+            ROMANISED_TERMS
+                .iter()
+                .filter(move |(term, _)| joined.iter().any(|word| word == term)),
+        )
+}
+
+/// Find Cantonese and Chinese profanity in `text`.
+///
+/// Returns what was found as a `rustrict` [`Type`], so it can be merged with `rustrict`'s own analysis,
+/// and every lexicon term that matched.
+#[must_use]
+pub fn analyze(text: &str) -> (Type, Vec<&'static str>) {
+    let folded: String = text.chars().map(|c| map(fold(c), VARIANTS)).collect();
+
+    let mut typ = Type::NONE;
+    let mut terms = Vec::new();
+
+    for &(term, levels) in folded.split(is_sentence_break).flat_map(segment_matches) {
+        if !terms.contains(&term) {
+            typ |= levels_to_type(levels);
+            terms.push(term);
+        }
+    }
+
+    (typ, terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+
+    fn terms(text: &str) -> Vec<&'static str> {
+        analyze(text).1
+    }
+
+    #[test]
+    fn finds_terms() {
+        assert_eq!(terms("屌"), ["屌"]);
+        assert_eq!(terms("你班人死全家"), ["死全家"]);
+        assert_eq!(terms("你老母"), ["你老母"]);
+        assert_eq!(terms("你條友仆街啦"), ["仆街"]);
+    }
+
+    #[test]
+    fn finds_simplified_and_homophones() {
+        assert_eq!(terms("操你妈"), ["操你媽"]);
+        assert_eq!(terms("吊你"), ["屌你"]);
+        assert_eq!(terms("刁你"), ["屌你"]);
+        assert_eq!(terms("扑街"), ["仆街"]);
+    }
+
+    #[test]
+    fn ignores_inserted_characters() {
+        assert_eq!(terms("屌 你"), ["屌", "屌你"]);
+        assert_eq!(terms("仆*街"), ["仆街"]);
+        assert_eq!(terms("死 x 全 x 家"), ["死全家"]);
+    }
+
+    #[test]
+    fn finds_mixed_and_romanised_terms() {
+        assert_eq!(terms("含L"), ["含l"]);
+        assert_eq!(terms("含 l 你"), ["含l"]);
+        assert_eq!(terms("ｐｋ街"), ["pk街"]);
+        assert_eq!(terms("d l l m"), ["dllm"]);
+        assert_eq!(terms("diu lei"), ["diu", "diulei"]);
+    }
+
+    #[test]
+    fn stays_within_a_sentence() {
+        assert!(terms("好想死，全家都唔理我").is_empty());
+        assert!(terms("仆\n街").is_empty());
+    }
+
+    #[test]
+    fn allows_ordinary_phrases() {
+        assert!(terms("我好掛住你老母親").is_empty());
+        assert!(terms("我有7頭牛").is_empty());
+        assert!(terms("我有七頭牛").is_empty());
+        assert!(terms("我唔會丟你").is_empty());
+        assert!(terms("包含 list 同埋其他").is_empty());
+        assert!(terms("西九龍").is_empty());
+    }
+}
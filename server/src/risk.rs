@@ -0,0 +1,148 @@
+use serde::Deserialize;
+
+/// A phrase that signals risk, and how much it adds to a post's score
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Term {
+    pub phrase: String,
+    pub weight: u32,
+}
+
+/// A local, offline classifier for self-harm and crisis signals in posts.
+///
+/// A post's score is the sum of the weights of every [`Term`] it contains.
+/// If it contains any term, each matching `intent_phrases` entry adds `intent_weight` on top.
+/// Posts scoring at least `threshold` are flagged.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskRules {
+    pub threshold: u32,
+    pub terms: Vec<Term>,
+    /// Phrases that suggest a plan or intent, like "tonight" or "i want to"
+    pub intent_phrases: Vec<String>,
+    pub intent_weight: u32,
+    /// Published as a pinned system comment on flagged posts
+    pub resources_message: String,
+}
+
+impl Default for RiskRules {
+    fn default() -> Self {
+        let terms = [
+            ("kill myself", 10),
+            ("end my life", 10),
+            ("suicide", 8),
+            ("suicidal", 8),
+            ("want to die", 8),
+            ("no reason to live", 8),
+            ("better off without me", 7),
+            ("self harm", 7),
+            ("cut myself", 7),
+            ("hurt myself", 6),
+            ("overdose", 6),
+            ("cant go on", 5),
+            ("hopeless", 3),
+            ("worthless", 3),
+            ("想死", 8),
+            ("自殺", 8),
+            ("自杀", 8),
+            ("唔想活", 8),
+            ("不想活", 8),
+            ("傷害自己", 7),
+            ("絕望", 3),
+        ]
+        .map(|(phrase, weight)| Term {
+            phrase: phrase.to_string(),
+            weight,
+        })
+        .to_vec();
+
+        let intent_phrases = [
+            "tonight",
+            "today",
+            "i want to",
+            "im going to",
+            "i will",
+            "plan to",
+            "goodbye",
+        ]
+        .map(str::to_string)
+        .to_vec();
+
+        Self {
+            threshold: 8,
+            terms,
+            intent_phrases,
+            intent_weight: 3,
+            resources_message: "If you are thinking about harming yourself, you are not alone and help is available right now. The Samaritans 24-hour hotline: 2896 0000. Suicide Prevention Services 24-hour hotline: 2382 0000. In an emergency, call 999.".to_string(),
+        }
+    }
+}
+
+/// The result of [`RiskRules::assess`]
+#[derive(Debug, Clone)]
+pub struct RiskAssessment {
+    pub score: u32,
+    /// Every term and intent phrase that matched
+    pub matched: Vec<String>,
+    pub flagged: bool,
+}
+
+impl RiskRules {
+    /// Score `text` against the lexicon and rules.
+    #[must_use]
+    pub fn assess(&self, text: &str) -> RiskAssessment {
+        let text = normalize(text);
+
+        let mut score = 0;
+        let mut matched = Vec::new();
+
+        for term in &self.terms {
+            if contains_phrase(&text, &normalize(&term.phrase)) {
+                score += term.weight;
+                matched.push(term.phrase.clone());
+            }
+        }
+
+        if !matched.is_empty() {
+            for phrase in &self.intent_phrases {
+                if contains_phrase(&text, &normalize(phrase)) {
+                    score += self.intent_weight;
+                    matched.push(phrase.clone());
+                }
+            }
+        }
+
+        RiskAssessment {
+            score,
+            matched,
+            flagged: score >= self.threshold,
+        }
+    }
+}
+
+/// Lowercase, drop apostrophes, and turn everything else that isn't a letter or digit into single spaces,
+/// so that "Can't   go on!" and "cant go on" match.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Match whole words for phrases with ascii letters, and any substring otherwise,
+/// since chinese text has no spaces between words.
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    if phrase.is_empty() {
+        return false;
+    }
+
+    if phrase.is_ascii() {
+        format!(" {text} ").contains(&format!(" {phrase} "))
+    } else {
+        text.contains(phrase)
+    }
+}
@@ -1,8 +1,17 @@
 pub mod get_posts;
+pub mod stream_advice;
 pub mod submit_post;
 
 pub mod add_comment;
+pub mod regenerate_advice;
 
+pub mod account;
 pub mod create_account;
 pub mod login;
+pub mod preferences;
+pub mod sessions;
 pub mod validate_session;
+
+pub mod admin;
+pub mod reports;
+pub mod risk_reviews;
@@ -0,0 +1,182 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, State};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::{HeaderMap, StatusCode};
+use axum::{Json, TypedHeader};
+
+use common::inputs::{InputDeleteAccount, InputPasswordChange};
+use common::{DeletedContent, DELETED_USERNAME};
+use server::{hash_password, password_matches, verify_auth, DBUser};
+use sqlx::{Pool, Sqlite};
+
+use crate::lockout::{Lockout, LockoutRules};
+
+/// Whether `password` is the password of `user`, counted by the [`LockoutRules`] like a login from `ip`
+async fn check_password(
+    user: &DBUser,
+    password: &str,
+    ip: &str,
+    lockout: &LockoutRules,
+    db_pool: &Pool<Sqlite>,
+) -> Result<bool, Lockout> {
+    let attempt = match lockout.attempt(&user.username, ip, db_pool).await.unwrap() {
+        Ok(attempt) => attempt,
+        Err(refused) => {
+            tracing::info!(
+                "{:?} from {ip} has to wait {}s to try their password",
+                user.username,
+                refused.retry_after
+            );
+            return Err(refused);
+        }
+    };
+
+    if password_matches(password, &user.hashed_password) {
+        lockout.record_success(attempt, db_pool).await.unwrap();
+        Ok(true)
+    } else {
+        lockout.record_failure(attempt, db_pool).await.unwrap();
+        Ok(false)
+    }
+}
+
+/// Input: [`InputPasswordChange`]
+///
+/// Output: `(StatusCode, String)`, replaces the password of the session's user and ends their other sessions,
+/// or [`Lockout`] after too many wrong passwords
+pub async fn change_password(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(lockout): State<Arc<LockoutRules>>,
+    Json(input): Json<InputPasswordChange>,
+) -> Result<(StatusCode, String), Lockout> {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return Ok((StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()));
+    };
+
+    if input.new_password.trim().is_empty() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "New password cannot be empty".to_string(),
+        ));
+    }
+
+    let user = sqlx::query_as::<_, DBUser>("SELECT * FROM users WHERE username = $1")
+        .bind(&session.username)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+
+    let ip = lockout.client_ip(addr, &headers);
+    if !check_password(&user, &input.current_password, &ip, &lockout, &db_pool).await? {
+        return Ok((StatusCode::FORBIDDEN, "Wrong password".to_string()));
+    }
+
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    sqlx::query("UPDATE users SET hashed_password = $1 WHERE username = $2")
+        .bind(hash_password(&input.new_password))
+        .bind(&session.username)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+    // whoever knew the old password could still be logged in elsewhere
+    let ended = sqlx::query("DELETE FROM sessions WHERE username = $1 AND id != $2")
+        .bind(&session.username)
+        .bind(session.id)
+        .execute(&mut *transaction)
+        .await
+        .unwrap()
+        .rows_affected();
+
+    transaction.commit().await.unwrap();
+
+    tracing::info!(
+        "{:?} changed their password, ending {ended} other sessions",
+        session.username
+    );
+
+    Ok((StatusCode::OK, "OK".to_string()))
+}
+
+/// Input: [`InputDeleteAccount`]
+///
+/// Output: `(StatusCode, String)`, deletes the session's user and every session of theirs,
+/// and deletes or anonymises their posts and comments, see [`DeletedContent`], or [`Lockout`] after too many wrong passwords
+pub async fn delete(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(lockout): State<Arc<LockoutRules>>,
+    Json(input): Json<InputDeleteAccount>,
+) -> Result<(StatusCode, String), Lockout> {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return Ok((StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()));
+    };
+
+    let user = sqlx::query_as::<_, DBUser>("SELECT * FROM users WHERE username = $1")
+        .bind(&session.username)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+
+    let ip = lockout.client_ip(addr, &headers);
+    if !check_password(&user, &input.password, &ip, &lockout, &db_pool).await? {
+        return Ok((StatusCode::FORBIDDEN, "Wrong password".to_string()));
+    }
+
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    let queries: &[&str] = match input.content {
+        // shadow-banned users' posts and comments stay hidden once their name is gone
+        DeletedContent::Anonymise => &[
+            "UPDATE posts SET username = $2, hidden = hidden OR $3 WHERE username = $1",
+            "UPDATE comments SET username = $2, hidden = hidden OR $3 WHERE username = $1",
+        ],
+        DeletedContent::Delete => &[
+            "DELETE FROM reports WHERE target = 'comment' AND target_id IN (SELECT id FROM comments WHERE username = $1 OR post_id IN (SELECT id FROM posts WHERE username = $1))",
+            "DELETE FROM reports WHERE target = 'post' AND target_id IN (SELECT id FROM posts WHERE username = $1)",
+            "DELETE FROM comments WHERE username = $1 OR post_id IN (SELECT id FROM posts WHERE username = $1)",
+            "DELETE FROM ai_jobs WHERE post_id IN (SELECT id FROM posts WHERE username = $1)",
+            "DELETE FROM risk_reviews WHERE post_id IN (SELECT id FROM posts WHERE username = $1)",
+            "DELETE FROM posts WHERE username = $1",
+        ],
+    };
+
+    for query in queries {
+        sqlx::query(query)
+            .bind(&session.username)
+            .bind(DELETED_USERNAME)
+            .bind(user.shadow_banned)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+
+    for query in [
+        "DELETE FROM sessions WHERE username = $1",
+        "DELETE FROM users WHERE username = $1",
+    ] {
+        sqlx::query(query)
+            .bind(&session.username)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+
+    transaction.commit().await.unwrap();
+
+    tracing::info!(
+        "{:?} deleted their account, content: {:?}",
+        session.username,
+        input.content
+    );
+
+    Ok((StatusCode::OK, "OK".to_string()))
+}
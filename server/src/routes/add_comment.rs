@@ -5,68 +5,136 @@ use axum::http::StatusCode;
 use axum::Json;
 use axum::TypedHeader;
 
-use chrono::Utc;
 use common::inputs::InputComment;
-use rustrict::{Censor, Type};
+use common::AuthorKind;
 use server::DBComment;
 
-use crate::db::{get_last_id, store_comment};
-use server::verify_auth;
+use crate::auth::{format_remaining, muted_for};
+use crate::db::{get_last_id, log_event, store_comment};
+use crate::jobs::{JobQueue, LOADING};
+use crate::moderation::{ContentKind, Moderation};
+use crate::spam::{Spam, SpamRules};
+use server::{verify_auth, JobKind};
 use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
 
 /// Input: [`InputComment`]
 ///
-/// Output: `(StatusCode, String)`
+/// Output: `(StatusCode, String)`, or [`Spam`] when the user comments too often or repeats themselves
+///
+/// When the post's author comments on their own post, the AI answers them in a new comment.
+/// `409 Conflict` while the AI is still working on the post, like in [`super::regenerate_advice`].
 pub async fn route(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(db_pool): State<Pool<Sqlite>>,
+    State(jobs): State<JobQueue>,
+    State(moderation): State<Arc<Moderation>>,
+    State(spam): State<Arc<SpamRules>>,
     Json(input): Json<InputComment>,
-) -> (StatusCode, String) {
+) -> Result<(StatusCode, String), Spam> {
     let session = verify_auth(&auth, &db_pool).await;
     if session.is_err() {
-        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+        return Ok((StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()));
     }
 
-    let analysis = Censor::from_str(&input.content).analyze();
-    if analysis.is((Type::SEXUAL & Type::MODERATE_OR_HIGHER) | Type::OFFENSIVE | Type::MEAN) {
-        tracing::info!("{:?} filter failed: {analysis:?}", session.unwrap().username);
-        return (StatusCode::FORBIDDEN, "Cannot say that".to_string());
+    if let Some(remaining) = muted_for(&session.as_ref().unwrap().username, &db_pool)
+        .await
+        .unwrap()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            format!("Muted for another {}", format_remaining(remaining)),
+        ));
+    }
+
+    let verdict = moderation.check(ContentKind::Comment, &input.content);
+    if !verdict.is_allowed() {
+        let username = session.unwrap().username;
+        tracing::info!("{username:?} filter failed: {verdict}");
+        log_event(&verdict.rejection(&username, &input.content), &db_pool)
+            .await
+            .unwrap();
+        return Ok((StatusCode::FORBIDDEN, "Cannot say that".to_string()));
     }
 
     if input.content.trim().is_empty() {
-        return (
+        return Ok((
             StatusCode::INTERNAL_SERVER_ERROR,
             "Cannot be empty".to_string(),
-        );
+        ));
     }
 
-    if sqlx::query("SELECT id FROM posts WHERE id = $1")
-        .bind(input.post_id)
-        .fetch_one(&db_pool)
+    let Ok(author) =
+        sqlx::query_scalar::<_, String>("SELECT username FROM posts WHERE id = $1 AND hidden = 0")
+            .bind(input.post_id)
+            .fetch_one(&db_pool)
+            .await
+    else {
+        return Ok((StatusCode::NOT_FOUND, "Post not found".to_string()));
+    };
+
+    let username = session.unwrap().username;
+
+    // a follow-up would stream into the same channel as the job that is still running
+    if username == author && jobs.is_working_on(input.post_id).await.unwrap_or(true) {
+        return Ok((
+            StatusCode::CONFLICT,
+            "AI is still working on this post".to_string(),
+        ));
+    }
+
+    if let Some(spam) = spam
+        .check_comment(input.post_id, &username, &input.content, &db_pool)
         .await
-        .is_err()
+        .unwrap()
     {
-        return (StatusCode::NOT_FOUND, "Post not found".to_string());
+        tracing::info!("{username:?} spam filter failed: {spam:?}");
+        log_event(&spam.rejection(&username, &input.content), &db_pool)
+            .await
+            .unwrap();
+        return Err(spam);
     }
 
-    let username = session.unwrap().username;
-
     tracing::debug!("recieved {:?}", input);
 
     let next_comment_id: u32 = get_last_id("comments", &db_pool).await + 1;
 
-    let comment = DBComment {
-        id: next_comment_id,
-        post_id: input.post_id,
-        created: Utc::now().timestamp(),
-        username: username.clone(),
-        content: input.content,
-    };
+    let comment = DBComment::new(next_comment_id, input.post_id, &username, &input.content);
 
     let res = store_comment(&comment, &db_pool).await;
 
-    match res {
-        Ok(_) => (StatusCode::OK, "OK".to_string()),
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    if let Err(err) = res {
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")));
+    }
+
+    if username != author {
+        return Ok((StatusCode::OK, "OK".to_string()));
+    }
+
+    if !jobs
+        .allows(input.post_id, JobKind::Followup)
+        .await
+        .unwrap_or(false)
+    {
+        return Ok((StatusCode::OK, "OK, no more AI follow-ups".to_string()));
     }
+
+    if jobs.is_full().await.unwrap_or(true) {
+        return Ok((StatusCode::OK, "OK, AI is busy".to_string()));
+    }
+
+    let loading = DBComment::new_system(
+        next_comment_id + 1,
+        input.post_id,
+        AuthorKind::Assistant,
+        LOADING,
+    );
+    store_comment(&loading, &db_pool).await.unwrap();
+
+    // the placeholder is replaced by a worker once the job finishes
+    jobs.enqueue(input.post_id, loading.id, JobKind::Followup)
+        .await
+        .unwrap();
+
+    Ok((StatusCode::OK, "OK, AI is answering".to_string()))
 }
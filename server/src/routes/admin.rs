@@ -0,0 +1,558 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use chrono::Utc;
+use common::inputs::{InputMute, InputReason, InputWord};
+use common::{ModerationAction, ModerationEvent, Role, WordList, WordListEntry};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::auth::{role_of, Admin, Moderator};
+use crate::db::{log_event, store_word};
+use crate::moderation::Moderation;
+use server::{DBModerationEvent, DBWord};
+
+/// `reason` without surrounding whitespace, unless nothing is left
+fn trimmed(reason: Option<String>) -> Option<String> {
+    reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty())
+}
+
+/// The reason given in an optional [`InputReason`] body
+fn reason(input: Option<Json<InputReason>>) -> Option<String> {
+    trimmed(input.map(|Json(input)| input.reason))
+}
+
+/// Input: `post_id` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, deletes the post with its comments, AI jobs, risk reviews and reports
+pub async fn delete_post(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(post_id): Path<u32>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    let Some((author, content)) = sqlx::query_as::<_, (String, String)>(
+        "DELETE FROM posts WHERE id = $1 RETURNING username, content",
+    )
+    .bind(post_id)
+    .fetch_optional(&mut *transaction)
+    .await
+    .unwrap() else {
+        return (StatusCode::NOT_FOUND, "Post not found".to_string());
+    };
+
+    for query in [
+        "DELETE FROM reports WHERE target = 'comment' AND target_id IN (SELECT id FROM comments WHERE post_id = $1)",
+        "DELETE FROM reports WHERE target = 'post' AND target_id = $1",
+        "DELETE FROM comments WHERE post_id = $1",
+        "DELETE FROM ai_jobs WHERE post_id = $1",
+        "DELETE FROM risk_reviews WHERE post_id = $1",
+    ] {
+        sqlx::query(query)
+            .bind(post_id)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+
+    transaction.commit().await.unwrap();
+
+    tracing::info!("{:?} deleted post {post_id}", moderator.username);
+
+    let mut event = DBModerationEvent::new(
+        &moderator.username,
+        ModerationAction::PostDeleted,
+        "post",
+        &post_id.to_string(),
+    );
+    event.reason = reason(input);
+    event.details = Some(format!("{author}: {content}"));
+    log_event(&event, &db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Input: `comment_id` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, deletes the comment with its AI jobs and reports
+pub async fn delete_comment(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(comment_id): Path<u32>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    let Some((author, content)) = sqlx::query_as::<_, (String, String)>(
+        "DELETE FROM comments WHERE id = $1 RETURNING username, content",
+    )
+    .bind(comment_id)
+    .fetch_optional(&mut *transaction)
+    .await
+    .unwrap() else {
+        return (StatusCode::NOT_FOUND, "Comment not found".to_string());
+    };
+
+    for query in [
+        "DELETE FROM reports WHERE target = 'comment' AND target_id = $1",
+        "DELETE FROM ai_jobs WHERE comment_id = $1",
+    ] {
+        sqlx::query(query)
+            .bind(comment_id)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+
+    transaction.commit().await.unwrap();
+
+    tracing::info!("{:?} deleted comment {comment_id}", moderator.username);
+
+    let mut event = DBModerationEvent::new(
+        &moderator.username,
+        ModerationAction::CommentDeleted,
+        "comment",
+        &comment_id.to_string(),
+    );
+    event.reason = reason(input);
+    event.details = Some(format!("{author}: {content}"));
+    log_event(&event, &db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Check that `username` exists and that `moderator` outranks them.
+async fn check_target(
+    moderator: &Moderator,
+    db_pool: &Pool<Sqlite>,
+    username: &str,
+) -> Result<(), (StatusCode, String)> {
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_one(db_pool)
+        .await
+        .unwrap();
+
+    if !exists {
+        return Err((StatusCode::NOT_FOUND, "User not found".to_string()));
+    }
+
+    if role_of(username, db_pool).await.unwrap() >= moderator.role {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Cannot sanction that user".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Change `username`'s state with `query`, which gets `username` as `$1` and `until` as `$2`,
+/// and record the change in the audit log with the acting moderator.
+async fn sanction(
+    moderator: &Moderator,
+    db_pool: &Pool<Sqlite>,
+    username: &str,
+    action: ModerationAction,
+    until: Option<i64>,
+    reason: Option<String>,
+    query: &str,
+) -> (StatusCode, String) {
+    if let Err(err) = check_target(moderator, db_pool, username).await {
+        return err;
+    }
+
+    sqlx::query(query)
+        .bind(username)
+        .bind(until)
+        .execute(db_pool)
+        .await
+        .unwrap();
+
+    let mut event = DBModerationEvent::new(&moderator.username, action, "user", username);
+    event.reason = reason;
+    event.details = until.map(|until| format!("until {until}"));
+    log_event(&event, db_pool).await.unwrap();
+
+    tracing::info!("{:?} {} {username:?}", moderator.username, action.as_str());
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Input: `username` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, bans the user and ends their session. Only users with a lower role can be banned.
+pub async fn ban(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    let res = sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::Banned,
+        None,
+        reason(input),
+        "UPDATE users SET banned = 1 WHERE username = $1",
+    )
+    .await;
+
+    if res.0 == StatusCode::OK {
+        sqlx::query("DELETE FROM sessions WHERE username = $1")
+            .bind(&username)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+    }
+
+    res
+}
+
+/// Input: `username` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, lets the user log in again
+pub async fn unban(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::Unbanned,
+        None,
+        reason(input),
+        "UPDATE users SET banned = 0 WHERE username = $1",
+    )
+    .await
+}
+
+/// Input: `username` in the path, [`InputMute`]
+///
+/// Output: `(StatusCode, String)`, stops the user from posting and commenting until the mute ends
+pub async fn mute(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    Json(input): Json<InputMute>,
+) -> (StatusCode, String) {
+    if input.minutes == 0 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Cannot mute for 0 minutes".to_string(),
+        );
+    }
+
+    let until = Utc::now().timestamp() + i64::from(input.minutes) * 60;
+
+    sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::Muted,
+        Some(until),
+        trimmed(input.reason),
+        "UPDATE users SET muted_until = $2 WHERE username = $1",
+    )
+    .await
+}
+
+/// Input: `username` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, ends the user's mute early
+pub async fn unmute(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::Unmuted,
+        None,
+        reason(input),
+        "UPDATE users SET muted_until = NULL WHERE username = $1",
+    )
+    .await
+}
+
+/// Input: `username` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, the user's posts and comments are only shown to themselves from now on
+pub async fn shadow_ban(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::ShadowBanned,
+        None,
+        reason(input),
+        "UPDATE users SET shadow_banned = 1 WHERE username = $1",
+    )
+    .await
+}
+
+/// Input: `username` in the path, optional [`InputReason`]
+///
+/// Output: `(StatusCode, String)`, the user's posts and comments are shown to everyone again
+pub async fn unshadow_ban(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    input: Option<Json<InputReason>>,
+) -> (StatusCode, String) {
+    sanction(
+        &moderator,
+        &db_pool,
+        &username,
+        ModerationAction::UnshadowBanned,
+        None,
+        reason(input),
+        "UPDATE users SET shadow_banned = 0 WHERE username = $1",
+    )
+    .await
+}
+
+/// Input: `username` in the path, [`Role`]
+///
+/// Output: `(StatusCode, String)`, gives the user the role, like promoting them to moderator
+pub async fn set_role(
+    admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(username): Path<String>,
+    Json(role): Json<Role>,
+) -> (StatusCode, String) {
+    // so that the last admin cannot lock everyone out
+    if username == admin.username {
+        return (
+            StatusCode::FORBIDDEN,
+            "Cannot change your own role".to_string(),
+        );
+    }
+
+    let updated = sqlx::query("UPDATE users SET role = $1 WHERE username = $2")
+        .bind(role.as_str())
+        .bind(&username)
+        .execute(&db_pool)
+        .await
+        .unwrap()
+        .rows_affected();
+
+    if updated == 0 {
+        return (StatusCode::NOT_FOUND, "User not found".to_string());
+    }
+
+    tracing::info!("{:?} made {username:?} {}", admin.username, role.as_str());
+
+    let mut event = DBModerationEvent::new(
+        &admin.username,
+        ModerationAction::RoleChanged,
+        "user",
+        &username,
+    );
+    event.details = Some(role.as_str().to_string());
+    log_event(&event, &db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Output: `(StatusCode, Json<Option<Vec<WordListEntry>>>)`, every word on the custom block and allow lists
+pub async fn list_words(
+    _admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, Json<Option<Vec<WordListEntry>>>) {
+    let words: Vec<DBWord> = sqlx::query_as::<_, DBWord>("SELECT * FROM word_lists ORDER BY id")
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+
+    let words = words
+        .into_iter()
+        .map(|word| WordListEntry {
+            id: word.id,
+            created: word.created,
+            list: WordList::from_db(&word.list),
+            word: word.word,
+            added_by: word.added_by,
+        })
+        .collect();
+
+    (StatusCode::OK, Json(Some(words)))
+}
+
+/// Input: [`InputWord`]
+///
+/// Output: `(StatusCode, String)`, adds the word to a list, which applies to new content right away
+pub async fn add_word(
+    admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(moderation): State<Arc<Moderation>>,
+    Json(input): Json<InputWord>,
+) -> (StatusCode, String) {
+    let word = input.word.trim().to_lowercase();
+
+    if word.is_empty() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Cannot be empty".to_string(),
+        );
+    }
+
+    let db_word = DBWord {
+        id: 0,
+        created: Utc::now().timestamp(),
+        word,
+        list: input.list.as_str().to_string(),
+        added_by: admin.username,
+    };
+
+    match store_word(&db_word, &db_pool).await {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            return (StatusCode::CONFLICT, "Already on the list".to_string());
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    }
+
+    tracing::info!(
+        "{:?} added {:?} to the {} list",
+        db_word.added_by,
+        db_word.word,
+        db_word.list
+    );
+
+    let mut event = DBModerationEvent::new(
+        &db_word.added_by,
+        ModerationAction::WordAdded,
+        "word",
+        &db_word.word,
+    );
+    event.details = Some(db_word.list.clone());
+    log_event(&event, &db_pool).await.unwrap();
+
+    moderation.reload_words(&db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Input: `word_id` in the path
+///
+/// Output: `(StatusCode, String)`, removes the word from its list, which applies to new content right away
+pub async fn remove_word(
+    admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(moderation): State<Arc<Moderation>>,
+    Path(word_id): Path<u32>,
+) -> (StatusCode, String) {
+    let Ok(word) = sqlx::query_as::<_, DBWord>("DELETE FROM word_lists WHERE id = $1 RETURNING *")
+        .bind(word_id)
+        .fetch_one(&db_pool)
+        .await
+    else {
+        return (StatusCode::NOT_FOUND, "Word not found".to_string());
+    };
+
+    tracing::info!(
+        "{:?} removed {:?} from the {} list",
+        admin.username,
+        word.word,
+        word.list
+    );
+
+    let mut event = DBModerationEvent::new(
+        &admin.username,
+        ModerationAction::WordRemoved,
+        "word",
+        &word.word,
+    );
+    event.details = Some(word.list);
+    log_event(&event, &db_pool).await.unwrap();
+
+    moderation.reload_words(&db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Output: `(StatusCode, String)`, reads the word lists from the database again, after they were edited by hand
+pub async fn reload_words(
+    admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(moderation): State<Arc<Moderation>>,
+) -> (StatusCode, String) {
+    match moderation.reload_words(&db_pool).await {
+        Ok(()) => {
+            tracing::info!("{:?} reloaded the word lists", admin.username);
+            (StatusCode::OK, "OK".to_string())
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    }
+}
+
+/// The query string of [`list_events`]. Every field is optional.
+#[derive(Debug, Deserialize)]
+pub struct EventFilter {
+    /// Events done by or to this user
+    user: Option<String>,
+    action: Option<ModerationAction>,
+    /// Unix timestamp, inclusive
+    from: Option<i64>,
+    /// Unix timestamp, exclusive
+    to: Option<i64>,
+    /// At most 1000, defaults to 100
+    limit: Option<u32>,
+}
+
+/// Input: [`EventFilter`] in the query string
+///
+/// Output: `(StatusCode, Json<Option<Vec<ModerationEvent>>>)`, the matching audit log entries, newest first
+pub async fn list_events(
+    _admin: Admin,
+    State(db_pool): State<Pool<Sqlite>>,
+    Query(filter): Query<EventFilter>,
+) -> (StatusCode, Json<Option<Vec<ModerationEvent>>>) {
+    let events: Vec<DBModerationEvent> = sqlx::query_as::<_, DBModerationEvent>("SELECT * FROM moderation_events WHERE ($1 IS NULL OR actor = $1 OR (target_kind = 'user' AND target = $1)) AND ($2 IS NULL OR action = $2) AND ($3 IS NULL OR created >= $3) AND ($4 IS NULL OR created < $4) ORDER BY id DESC LIMIT $5")
+        .bind(&filter.user)
+        .bind(filter.action.map(ModerationAction::as_str))
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.limit.unwrap_or(100).min(1000))
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+
+    let events = events
+        .into_iter()
+        .filter_map(|event| {
+            Some(ModerationEvent {
+                id: event.id,
+                created: event.created,
+                actor: event.actor,
+                action: ModerationAction::from_db(&event.action)?,
+                target_kind: event.target_kind,
+                target: event.target,
+                reason: event.reason,
+                details: event.details,
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(Some(events)))
+}
@@ -1,76 +1,117 @@
-use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHasher};
-
 use axum::extract::State;
+use axum::headers::UserAgent;
 use axum::http::StatusCode;
-use axum::Json;
+use axum::{Json, TypedHeader};
 
 use chrono::Utc;
-use rand::rngs::OsRng;
 use sqlx::Pool;
 use sqlx::Sqlite;
 
-use common::User;
-use server::DBUser;
+use common::username::{username_skeleton, validate_username, UsernameError};
+use common::{Role, User};
+use server::{create_session, hash_password, DBUser};
 use sqlx::sqlite::SqliteQueryResult;
 
-use crate::db::store_new_user;
+use std::sync::Arc;
+
+use crate::db::{log_event, store_new_user};
+use crate::moderation::{ContentKind, Moderation};
+
+type Rejection = (StatusCode, Json<UsernameError>);
+
+fn reject(reason: UsernameError) -> Rejection {
+    let status = match reason {
+        UsernameError::Reserved | UsernameError::Inappropriate => StatusCode::FORBIDDEN,
+        UsernameError::TooSimilar | UsernameError::Taken => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    (status, Json(reason))
+}
 
 /// Input: [`User`]
 ///
-/// Output: `(StatusCode, Json<Option<String>>)`
+/// Output: `(StatusCode, Json<Option<String>>)`, or `(StatusCode, Json<UsernameError>)` if the username was rejected
 pub async fn route(
+    user_agent: Option<TypedHeader<UserAgent>>,
     State(db_pool): State<Pool<Sqlite>>,
+    State(moderation): State<Arc<Moderation>>,
     Json(input): Json<User>,
-) -> (StatusCode, Json<Option<String>>) {
+) -> Result<(StatusCode, Json<Option<String>>), Rejection> {
     tracing::debug!("recieved {:?}", input);
 
-    if input.username.trim().is_empty() {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(None));
-    }
+    let username = validate_username(&input.username).map_err(reject)?;
 
     if input.password.trim().is_empty() {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(None));
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(None)));
     }
 
-    let salt: SaltString = SaltString::generate(&mut OsRng);
-    let hashed_password: String = Argon2::default()
-        .hash_password(input.password.as_bytes(), &salt)
-        .unwrap()
-        .to_string();
+    let verdict = moderation.check(ContentKind::Username, &username);
+    if !verdict.is_allowed() {
+        tracing::info!("{username:?} filter failed: {verdict}");
+        log_event(&verdict.rejection(&username, &username), &db_pool)
+            .await
+            .unwrap();
+        return Err(reject(UsernameError::Inappropriate));
+    }
+
+    let skeleton = username_skeleton(&username);
+
+    let similar: Option<String> =
+        sqlx::query_scalar("SELECT username FROM users WHERE skeleton = $1")
+            .bind(&skeleton)
+            .fetch_optional(&db_pool)
+            .await
+            .unwrap();
+
+    if let Some(similar) = similar {
+        // the same name is reported as taken, instead of only looking like it
+        if similar == username {
+            return Err(reject(UsernameError::Taken));
+        }
+        tracing::info!("{username:?} looks like {similar:?}");
+        return Err(reject(UsernameError::TooSimilar));
+    }
+
+    let hashed_password: String = hash_password(&input.password);
 
     let new_user: DBUser = DBUser {
         created: Utc::now().timestamp(),
-        username: input.username.trim().to_owned(),
+        username: username.clone(),
         hashed_password: hashed_password.trim().to_owned(),
+        role: Role::User.as_str().to_string(),
+        banned: false,
+        muted_until: None,
+        shadow_banned: false,
+        skeleton,
+        uncensored: false,
     };
 
     let res: Result<SqliteQueryResult, sqlx::Error> = store_new_user(&new_user, &db_pool).await;
 
     match res {
         Ok(_) => {
-            let new_session_id = SaltString::generate(&mut OsRng).to_string();
-
-            sqlx::query("INSERT OR REPLACE INTO sessions (username, id) VALUES ($1, $2)")
-                .bind(input.username)
-                .bind(&new_session_id)
-                .execute(&db_pool)
-                .await
-                .unwrap();
+            let new_session_id = create_session(
+                &username,
+                user_agent.as_ref().map(|agent| agent.as_str()),
+                &db_pool,
+            )
+            .await
+            .unwrap();
 
-            (StatusCode::OK, Json(Some(new_session_id)))
+            Ok((StatusCode::OK, Json(Some(new_session_id))))
         }
         Err(err) => {
             if let sqlx::Error::Database(err) = err {
                 if err.is_unique_violation() {
-                    (StatusCode::CONFLICT, Json(None))
+                    Err(reject(UsernameError::Taken))
                 } else {
                     tracing::error!("{err:?}");
-                    (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+                    Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(None)))
                 }
             } else {
                 tracing::error!("{err:?}");
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+                Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(None)))
             }
         }
     }
@@ -1,24 +1,55 @@
-use axum::{extract::State, http::StatusCode, Json};
-use server::{FromDBComment, FromDBPost};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::{extract::State, http::StatusCode, Json, TypedHeader};
+use server::{verify_auth, FromDBComment, FromDBPost};
 use sqlx::{Pool, Sqlite};
 
 use common::{Comment, Post};
+
+use crate::moderation::censor;
 use server::{DBComment, DBPost};
 
 /// Output: `(StatusCode, Json<Option<Vec<Post>>>)`
+///
+/// Posts and comments hidden by reports are left out.
+/// Posts and comments by shadow-banned users are left out, unless the optional session id is theirs.
+/// Inappropriate words are censored, unless the optional session id's user has opted out.
 #[rustfmt::skip]
 pub async fn route(
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
     State(db_pool): State<Pool<Sqlite>>
 ) -> (StatusCode, Json<Option<Vec<Post>>>) {
-    let db_posts: Vec<DBPost> = sqlx::query_as::<_, DBPost>("SELECT * from posts")
+    let viewer: Option<String> = match auth {
+        Some(TypedHeader(auth)) => verify_auth(&auth, &db_pool).await.ok().map(|session| session.username),
+        None => None,
+    };
+
+    let uncensored: bool = sqlx::query_scalar("SELECT uncensored FROM users WHERE username = $1")
+        .bind(&viewer)
+        .fetch_optional(&db_pool)
+        .await
+        .unwrap()
+        .unwrap_or(false);
+
+    let mut db_posts: Vec<DBPost> = sqlx::query_as::<_, DBPost>("SELECT * from posts WHERE hidden = 0 AND (username = $1 OR username NOT IN (SELECT username FROM users WHERE shadow_banned = 1))")
+        .bind(&viewer)
         .fetch_all(&db_pool)
         .await
         .unwrap();
-    let db_comments: Vec<DBComment> = sqlx::query_as::<_, DBComment>("SELECT * from comments")
+    let mut db_comments: Vec<DBComment> = sqlx::query_as::<_, DBComment>("SELECT * from comments WHERE hidden = 0 AND (username = $1 OR username NOT IN (SELECT username FROM users WHERE shadow_banned = 1)) ORDER BY pinned DESC, id")
+        .bind(&viewer)
         .fetch_all(&db_pool)
         .await
         .unwrap();
 
+    if !uncensored {
+        for post in &mut db_posts {
+            post.content = censor(&post.content);
+        }
+        for comment in &mut db_comments {
+            comment.content = censor(&comment.content);
+        }
+    }
+
     let mut posts: Vec<Post> = Vec::with_capacity(db_posts.len());
 
     for db_post in db_posts {
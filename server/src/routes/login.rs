@@ -1,51 +1,88 @@
-use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::Json;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::{ConnectInfo, State};
+use axum::headers::UserAgent;
+use axum::http::{HeaderMap, StatusCode};
+use axum::{Json, TypedHeader};
 
-use rand::rngs::OsRng;
 use sqlx::Pool;
 use sqlx::Sqlite;
 
+use common::username::normalize_username;
 use common::User;
-use server::DBUser;
+use server::{create_session, hash_password, password_matches, DBUser};
+
+use crate::lockout::{Lockout, LockoutRules};
+
+/// Checked against when the username does not exist, so that it takes as long as a wrong password
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
 
 /// Input: `new_user: Json<User>`
 ///
 /// Output: `(StatusCode, Json<Option<String>>)`
+///
+/// Unknown usernames and wrong passwords get the same `401 Unauthorized`, so that accounts cannot be found by trying names.
+/// Too many failures for the username or from the client's address are refused, see [`LockoutRules`].
 pub async fn route(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    user_agent: Option<TypedHeader<UserAgent>>,
     State(db_pool): State<Pool<Sqlite>>,
+    State(lockout): State<Arc<LockoutRules>>,
     Json(input): Json<User>,
-) -> (StatusCode, Json<Option<String>>) {
-    let fetched_user = sqlx::query_as::<_, DBUser>("SELECT * from users WHERE username = $1")
-        .bind(input.username)
-        .fetch_one(&db_pool)
-        .await;
-
-    let Ok(user) = fetched_user else {
-        return (StatusCode::NOT_FOUND, Json(None));
+) -> Result<(StatusCode, Json<Option<String>>), Lockout> {
+    let username = normalize_username(&input.username);
+    let ip = lockout.client_ip(addr, &headers);
+
+    // refused before hashing, so that guesses cost the server nothing
+    let attempt = match lockout.attempt(&username, &ip, &db_pool).await.unwrap() {
+        Ok(attempt) => attempt,
+        Err(refused) => {
+            tracing::info!(
+                "{username:?} from {ip} has to wait {}s to log in",
+                refused.retry_after
+            );
+            return Err(refused);
+        }
     };
 
-    let hashed: PasswordHash<'_> = PasswordHash::new(&user.hashed_password).unwrap();
+    // accounts created before usernames were normalised can still log in with the name exactly as they signed up
+    let fetched_user = sqlx::query_as::<_, DBUser>(
+        "SELECT * from users WHERE username IN ($1, $2) ORDER BY username = $1 DESC LIMIT 1",
+    )
+    .bind(&username)
+    .bind(&input.username)
+    .fetch_optional(&db_pool)
+    .await
+    .unwrap();
 
-    if Argon2::default()
-        .verify_password(input.password.as_bytes(), &hashed)
-        .is_err()
-    {
-        return (StatusCode::UNAUTHORIZED, Json(None));
-    }
+    let hashed_password = fetched_user.as_ref().map_or_else(
+        || DUMMY_HASH.get_or_init(|| hash_password("")).as_str(),
+        |user| user.hashed_password.as_str(),
+    );
+    let password_matches = password_matches(&input.password, hashed_password);
 
-    let new_session_id = SaltString::generate(&mut OsRng).to_string();
+    let Some(user) = fetched_user.filter(|_| password_matches) else {
+        lockout.record_failure(attempt, &db_pool).await.unwrap();
+        return Ok((StatusCode::UNAUTHORIZED, Json(None)));
+    };
 
-    let query = sqlx::query("INSERT OR REPLACE INTO sessions (username, id) VALUES ($1, $2)")
-        .bind(user.username)
-        .bind(&new_session_id)
-        .execute(&db_pool)
-        .await;
+    lockout.record_success(attempt, &db_pool).await.unwrap();
 
-    match query {
-        Ok(_) => (StatusCode::OK, Json(Some(new_session_id))),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(None)),
+    if user.banned {
+        tracing::info!("banned user {:?} tried to log in", user.username);
+        return Ok((StatusCode::FORBIDDEN, Json(None)));
+    }
+
+    match create_session(
+        &user.username,
+        user_agent.as_ref().map(|agent| agent.as_str()),
+        &db_pool,
+    )
+    .await
+    {
+        Ok(new_session_id) => Ok((StatusCode::OK, Json(Some(new_session_id)))),
+        Err(_) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(None))),
     }
 }
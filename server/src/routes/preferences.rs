@@ -0,0 +1,50 @@
+use axum::extract::State;
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::StatusCode;
+use axum::{Json, TypedHeader};
+
+use common::Preferences;
+use server::verify_auth;
+use sqlx::{Pool, Sqlite};
+
+/// Output: `(StatusCode, Json<Option<Preferences>>)`, the preferences of the session's user
+pub async fn get(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, Json<Option<Preferences>>) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, Json(None));
+    };
+
+    let uncensored: bool = sqlx::query_scalar("SELECT uncensored FROM users WHERE username = $1")
+        .bind(&session.username)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+
+    (StatusCode::OK, Json(Some(Preferences { uncensored })))
+}
+
+/// Input: [`Preferences`]
+///
+/// Output: `(StatusCode, String)`, replaces the preferences of the session's user
+pub async fn set(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    Json(input): Json<Preferences>,
+) -> (StatusCode, String) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    };
+
+    sqlx::query("UPDATE users SET uncensored = $1 WHERE username = $2")
+        .bind(input.uncensored)
+        .bind(&session.username)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    tracing::info!("{:?} set {input:?}", session.username);
+
+    (StatusCode::OK, "OK".to_string())
+}
@@ -0,0 +1,81 @@
+use axum::extract::{Path, State};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::StatusCode;
+use axum::TypedHeader;
+
+use common::AuthorKind;
+use sqlx::{Pool, Sqlite};
+
+use crate::db::{get_last_id, store_comment};
+use crate::jobs::{JobQueue, LOADING};
+use server::{verify_auth, DBComment, JobKind};
+
+/// Input: `post_id` in the path
+///
+/// Output: `(StatusCode, String)`
+///
+/// Asks the AI for new advice on the post, in a new comment. Earlier advice is kept.
+pub async fn route(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(jobs): State<JobQueue>,
+    Path(post_id): Path<u32>,
+) -> (StatusCode, String) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    };
+
+    let Ok(author) = sqlx::query_scalar::<_, String>("SELECT username FROM posts WHERE id = $1")
+        .bind(post_id)
+        .fetch_one(&db_pool)
+        .await
+    else {
+        return (StatusCode::NOT_FOUND, "Post not found".to_string());
+    };
+
+    if session.username != author {
+        return (StatusCode::FORBIDDEN, "Not your post".to_string());
+    }
+
+    if jobs.is_working_on(post_id).await.unwrap_or(true) {
+        return (
+            StatusCode::CONFLICT,
+            "AI is still working on this post".to_string(),
+        );
+    }
+
+    if !jobs
+        .allows(post_id, JobKind::Regeneration)
+        .await
+        .unwrap_or(false)
+    {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            "No more regenerations for this post".to_string(),
+        );
+    }
+
+    if jobs.is_full().await.unwrap_or(true) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "AI is busy, try again later".to_string(),
+        );
+    }
+
+    let new_comment_id: u32 = get_last_id("comments", &db_pool).await + 1;
+
+    let loading: DBComment =
+        DBComment::new_system(new_comment_id, post_id, AuthorKind::Assistant, LOADING);
+    if let Err(err) = store_comment(&loading, &db_pool).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}"));
+    }
+
+    // the placeholder is replaced by a worker once the job finishes
+    jobs.enqueue(post_id, new_comment_id, JobKind::Regeneration)
+        .await
+        .unwrap();
+
+    tracing::info!("post {post_id}: regenerating advice");
+
+    (StatusCode::OK, "OK, AI is answering".to_string())
+}
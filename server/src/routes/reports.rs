@@ -0,0 +1,313 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::StatusCode;
+use axum::{Json, TypedHeader};
+
+use chrono::Utc;
+use common::inputs::InputReport;
+use common::{ModerationAction, Report, ReportReason, ReportTarget};
+use sqlx::{Pool, Sqlite};
+
+use crate::auth::Moderator;
+use crate::config::ReportRules;
+use crate::db::{log_event, store_report};
+use server::{verify_auth, DBModerationEvent, DBReport, ReportState, SYSTEM_ACTOR};
+
+/// The table a [`ReportTarget`] is stored in
+fn table(target: ReportTarget) -> &'static str {
+    match target {
+        ReportTarget::Post => "posts",
+        ReportTarget::Comment => "comments",
+    }
+}
+
+async fn open_reports(
+    target: &str,
+    target_id: u32,
+    db_pool: &Pool<Sqlite>,
+) -> Result<u32, sqlx::error::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM reports WHERE target = $1 AND target_id = $2 AND state = $3",
+    )
+    .bind(target)
+    .bind(target_id)
+    .bind(ReportState::Open)
+    .fetch_one(db_pool)
+    .await
+}
+
+/// Input: [`InputReport`]
+///
+/// Output: `(StatusCode, String)`
+///
+/// Once a post or comment has enough open reports, it is hidden until a moderator handles them.
+pub async fn create(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(rules): State<Arc<ReportRules>>,
+    Json(input): Json<InputReport>,
+) -> (StatusCode, String) {
+    let session = verify_auth(&auth, &db_pool).await;
+    if session.is_err() {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    }
+
+    let details = input
+        .details
+        .as_ref()
+        .map(|details| details.trim().to_string())
+        .filter(|details| !details.is_empty());
+
+    if details
+        .as_ref()
+        .is_some_and(|details| details.chars().count() > rules.max_details_length)
+    {
+        return (StatusCode::BAD_REQUEST, "Details too long".to_string());
+    }
+
+    let table = table(input.target);
+
+    let exists: bool =
+        sqlx::query_scalar(&format!("SELECT COUNT(*) > 0 FROM {table} WHERE id = $1"))
+            .bind(input.target_id)
+            .fetch_one(&db_pool)
+            .await
+            .unwrap_or(false);
+
+    if !exists {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("{:?} not found", input.target),
+        );
+    }
+
+    let username = session.unwrap().username;
+
+    tracing::debug!("recieved {:?}", input);
+
+    let report = DBReport {
+        id: 0,
+        created: Utc::now().timestamp(),
+        target: input.target.as_str().to_string(),
+        target_id: input.target_id,
+        reporter: username.clone(),
+        reason: input.reason.as_str().to_string(),
+        details,
+        state: ReportState::Open,
+        handled_by: None,
+        handled_at: None,
+    };
+
+    match store_report(&report, &db_pool).await {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            return (StatusCode::CONFLICT, "Already reported".to_string());
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    }
+
+    tracing::info!(
+        "{username:?} reported {} {} for {:?}",
+        report.target,
+        report.target_id,
+        input.reason
+    );
+
+    let target_id = report.target_id.to_string();
+
+    let mut event = DBModerationEvent::new(
+        &username,
+        ModerationAction::Reported,
+        &report.target,
+        &target_id,
+    );
+    event.reason = Some(report.reason.clone());
+    event.details = report.details.clone();
+    log_event(&event, &db_pool).await.unwrap();
+
+    let open = open_reports(&report.target, report.target_id, &db_pool)
+        .await
+        .unwrap();
+
+    if open < rules.hide_threshold {
+        return (StatusCode::OK, "OK, reported".to_string());
+    }
+
+    sqlx::query(&format!("UPDATE {table} SET hidden = 1 WHERE id = $1"))
+        .bind(report.target_id)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    tracing::warn!(
+        "{} {} hidden after {open} reports",
+        report.target,
+        report.target_id
+    );
+
+    let mut event = DBModerationEvent::new(
+        SYSTEM_ACTOR,
+        ModerationAction::AutoHidden,
+        &report.target,
+        &target_id,
+    );
+    event.details = Some(format!("{open} open reports"));
+    log_event(&event, &db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK, reported and hidden".to_string())
+}
+
+/// Output: `(StatusCode, Json<Option<Vec<Report>>>)`, the reports nobody has handled yet, oldest first
+pub async fn list(
+    _moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, Json<Option<Vec<Report>>>) {
+    let db_reports: Vec<DBReport> =
+        sqlx::query_as::<_, DBReport>("SELECT * FROM reports WHERE state = $1 ORDER BY id")
+            .bind(ReportState::Open)
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+
+    let mut reports: Vec<Report> = Vec::with_capacity(db_reports.len());
+
+    for report in db_reports {
+        let target = ReportTarget::from_db(&report.target);
+
+        let Ok((author, content, hidden)) = sqlx::query_as::<_, (String, String, bool)>(&format!(
+            "SELECT username, content, hidden FROM {} WHERE id = $1",
+            table(target)
+        ))
+        .bind(report.target_id)
+        .fetch_one(&db_pool)
+        .await
+        else {
+            continue;
+        };
+
+        let open_reports = open_reports(&report.target, report.target_id, &db_pool)
+            .await
+            .unwrap();
+
+        reports.push(Report {
+            id: report.id,
+            created: report.created,
+            target,
+            target_id: report.target_id,
+            author,
+            content,
+            hidden,
+            reporter: report.reporter,
+            reason: ReportReason::from_db(&report.reason),
+            details: report.details,
+            open_reports,
+        });
+    }
+
+    (StatusCode::OK, Json(Some(reports)))
+}
+
+/// Mark every open report on the same item as report `report_id` as `state`, and hide or show the item.
+async fn handle(
+    username: &str,
+    db_pool: &Pool<Sqlite>,
+    report_id: u32,
+    state: ReportState,
+    hidden: bool,
+) -> (StatusCode, String) {
+    let Ok(report) =
+        sqlx::query_as::<_, DBReport>("SELECT * FROM reports WHERE id = $1 AND state = $2")
+            .bind(report_id)
+            .bind(ReportState::Open)
+            .fetch_one(db_pool)
+            .await
+    else {
+        return (StatusCode::NOT_FOUND, "Report not found".to_string());
+    };
+
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    let handled = sqlx::query("UPDATE reports SET state = $1, handled_by = $2, handled_at = $3 WHERE target = $4 AND target_id = $5 AND state = $6")
+        .bind(state)
+        .bind(username)
+        .bind(Utc::now().timestamp())
+        .bind(&report.target)
+        .bind(report.target_id)
+        .bind(ReportState::Open)
+        .execute(&mut *transaction)
+        .await
+        .unwrap()
+        .rows_affected();
+
+    sqlx::query(&format!(
+        "UPDATE {} SET hidden = $1 WHERE id = $2",
+        table(ReportTarget::from_db(&report.target))
+    ))
+    .bind(hidden)
+    .bind(report.target_id)
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    transaction.commit().await.unwrap();
+
+    tracing::info!(
+        "{username:?} marked {handled} reports on {} {} as {state:?}",
+        report.target,
+        report.target_id
+    );
+
+    let action = match state {
+        ReportState::Dismissed => ModerationAction::ReportDismissed,
+        _ => ModerationAction::ReportResolved,
+    };
+
+    let mut event = DBModerationEvent::new(
+        username,
+        action,
+        &report.target,
+        &report.target_id.to_string(),
+    );
+    event.details = Some(format!("{handled} reports"));
+    log_event(&event, db_pool).await.unwrap();
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Input: `report_id` in the path
+///
+/// Output: `(StatusCode, String)`, resolves every open report on the reported item, which stays hidden
+pub async fn resolve(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(report_id): Path<u32>,
+) -> (StatusCode, String) {
+    handle(
+        &moderator.username,
+        &db_pool,
+        report_id,
+        ReportState::Resolved,
+        true,
+    )
+    .await
+}
+
+/// Input: `report_id` in the path
+///
+/// Output: `(StatusCode, String)`, dismisses every open report on the reported item, which is shown again
+pub async fn dismiss(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(report_id): Path<u32>,
+) -> (StatusCode, String) {
+    handle(
+        &moderator.username,
+        &db_pool,
+        report_id,
+        ReportState::Dismissed,
+        false,
+    )
+    .await
+}
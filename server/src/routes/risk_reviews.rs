@@ -0,0 +1,89 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use chrono::Utc;
+use common::{ModerationAction, RiskReview};
+use sqlx::{Pool, Sqlite};
+
+use crate::auth::Moderator;
+use crate::db::log_event;
+use server::{DBModerationEvent, DBPost, DBRiskReview};
+
+/// Output: `(StatusCode, Json<Option<Vec<RiskReview>>>)`, the flagged posts nobody has reviewed yet, oldest first
+pub async fn list(
+    _moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, Json<Option<Vec<RiskReview>>>) {
+    let db_reviews: Vec<DBRiskReview> = sqlx::query_as::<_, DBRiskReview>(
+        "SELECT * FROM risk_reviews WHERE reviewed_by IS NULL ORDER BY id",
+    )
+    .fetch_all(&db_pool)
+    .await
+    .unwrap();
+
+    let mut reviews: Vec<RiskReview> = Vec::with_capacity(db_reviews.len());
+
+    for review in db_reviews {
+        let Ok(post) = sqlx::query_as::<_, DBPost>("SELECT * FROM posts WHERE id = $1")
+            .bind(review.post_id)
+            .fetch_one(&db_pool)
+            .await
+        else {
+            continue;
+        };
+
+        reviews.push(RiskReview {
+            id: review.id,
+            post_id: review.post_id,
+            created: review.created,
+            username: post.username,
+            content: post.content,
+            score: review.score,
+            matched: serde_json::from_str(&review.matched).unwrap_or_default(),
+            reviewed_by: review.reviewed_by,
+        });
+    }
+
+    (StatusCode::OK, Json(Some(reviews)))
+}
+
+/// Input: `review_id` in the path
+///
+/// Output: `(StatusCode, String)`, marks the review as done by the moderator
+pub async fn resolve(
+    moderator: Moderator,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(review_id): Path<u32>,
+) -> (StatusCode, String) {
+    let username = moderator.username;
+
+    let res = sqlx::query(
+        "UPDATE risk_reviews SET reviewed_by = $2, reviewed_at = $3 WHERE id = $1 AND reviewed_by IS NULL",
+    )
+    .bind(review_id)
+    .bind(&username)
+    .bind(Utc::now().timestamp())
+    .execute(&db_pool)
+    .await;
+
+    match res {
+        Ok(res) if res.rows_affected() == 0 => {
+            (StatusCode::NOT_FOUND, "Review not found".to_string())
+        }
+        Ok(_) => {
+            tracing::info!("{username:?} reviewed risk review {review_id}");
+
+            let event = DBModerationEvent::new(
+                &username,
+                ModerationAction::RiskReviewed,
+                "risk_review",
+                &review_id.to_string(),
+            );
+            log_event(&event, &db_pool).await.unwrap();
+
+            (StatusCode::OK, "OK".to_string())
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    }
+}
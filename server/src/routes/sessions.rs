@@ -0,0 +1,110 @@
+use axum::extract::{Path, State};
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::StatusCode;
+use axum::{Json, TypedHeader};
+
+use chrono::Utc;
+use common::Session;
+use server::{verify_auth, DBSession};
+use sqlx::{Pool, Sqlite};
+
+/// Output: `(StatusCode, String)`, ends the bearer's session
+pub async fn logout(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, String) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    };
+
+    sqlx::query("DELETE FROM sessions WHERE id = $1")
+        .bind(session.id)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    tracing::info!("{:?} logged out", session.username);
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Output: `(StatusCode, Json<Option<Vec<Session>>>)`, the unexpired sessions of the bearer's user, most recently used first
+pub async fn list(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, Json<Option<Vec<Session>>>) {
+    let Ok(current) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, Json(None));
+    };
+
+    let sessions = sqlx::query_as::<_, DBSession>(
+        "SELECT * FROM sessions WHERE username = $1 AND expires > $2 ORDER BY last_seen DESC",
+    )
+    .bind(&current.username)
+    .bind(Utc::now().timestamp())
+    .fetch_all(&db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|session| Session {
+        id: session.id,
+        created: session.created,
+        last_seen: session.last_seen,
+        user_agent: session.user_agent,
+        current: session.id == current.id,
+    })
+    .collect();
+
+    (StatusCode::OK, Json(Some(sessions)))
+}
+
+/// Input: `id` in the path
+///
+/// Output: `(StatusCode, String)`, ends one of the bearer's user's sessions, like one on a lost phone
+pub async fn revoke(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+    Path(id): Path<u32>,
+) -> (StatusCode, String) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    };
+
+    // users can only end their own sessions
+    let deleted = sqlx::query("DELETE FROM sessions WHERE id = $1 AND username = $2")
+        .bind(id)
+        .bind(&session.username)
+        .execute(&db_pool)
+        .await
+        .unwrap()
+        .rows_affected();
+
+    if deleted == 0 {
+        return (StatusCode::NOT_FOUND, format!("No session {id}"));
+    }
+
+    tracing::info!("{:?} ended their session {id}", session.username);
+
+    (StatusCode::OK, "OK".to_string())
+}
+
+/// Output: `(StatusCode, String)`, ends every session of the bearer's user, including the bearer's
+pub async fn revoke_all(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(db_pool): State<Pool<Sqlite>>,
+) -> (StatusCode, String) {
+    let Ok(session) = verify_auth(&auth, &db_pool).await else {
+        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+    };
+
+    let deleted = sqlx::query("DELETE FROM sessions WHERE username = $1")
+        .bind(&session.username)
+        .execute(&db_pool)
+        .await
+        .unwrap()
+        .rows_affected();
+
+    tracing::info!("{:?} logged out of {deleted} sessions", session.username);
+
+    (StatusCode::OK, "OK".to_string())
+}
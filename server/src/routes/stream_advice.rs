@@ -0,0 +1,116 @@
+use std::convert::Infallible;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+
+use async_stream::stream;
+use futures_util::Stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use common::Comment;
+use server::{DBComment, FromDBComment};
+use sqlx::{Pool, Sqlite};
+
+use crate::advice::hub::{AdviceEvent, AdviceHub};
+use crate::jobs::LOADING;
+use crate::moderation::censor;
+
+/// Input: `post_id` in the path
+///
+/// Output: server-sent events. `chunk` events carry an [`common::AdviceChunk`] as the advice is generated,
+/// a `reset` event means the chunks so far should be thrown away,
+/// and the last event, `done`, carries the finished AI [`Comment`].
+///
+/// If the post's advice is already finished, only `done` is sent.
+///
+/// Everything is censored, since `EventSource` cannot send a session id to opt out with.
+/// For the same reason, hidden posts and posts by shadow-banned users are `404 Not Found` for everyone, like in [`super::get_posts`].
+pub async fn route(
+    Path(post_id): Path<u32>,
+    State(db_pool): State<Pool<Sqlite>>,
+    State(hub): State<AdviceHub>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    if sqlx::query("SELECT id FROM posts WHERE id = $1 AND hidden = 0 AND username NOT IN (SELECT username FROM users WHERE shadow_banned = 1)")
+        .bind(post_id)
+        .fetch_one(&db_pool)
+        .await
+        .is_err()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // subscribe before checking the database, so that an update in between is not missed
+    let mut receiver = hub.subscribe(post_id);
+
+    let latest = sqlx::query_as::<_, DBComment>(
+        "SELECT * FROM comments WHERE post_id = $1 AND author_kind = 'assistant' ORDER BY id DESC",
+    )
+    .bind(post_id)
+    .fetch_optional(&db_pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let finished: Option<Comment> = match latest {
+        Some(comment) if comment.content != LOADING => Some(Comment::from_db(&comment)),
+        Some(_) => None,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let events = stream! {
+        if let Some(comment) = finished {
+            drop(receiver);
+            hub.release(post_id);
+
+            yield Ok(done_event(&comment));
+            return;
+        }
+
+        // the whole advice is censored every time, since a word can be split between chunks
+        let mut raw = String::new();
+        let mut sent = String::new();
+
+        loop {
+            match receiver.recv().await {
+                Ok(AdviceEvent::Chunk(mut chunk)) => {
+                    raw.push_str(&chunk.text);
+                    let censored = censor(&raw);
+
+                    // a word that was only censored once it was finished changes text that was already sent
+                    if let Some(new) = censored.strip_prefix(&sent) {
+                        chunk.text = new.to_string();
+                    } else {
+                        yield Ok(Event::default().event("reset").data(""));
+                        chunk.text = censored.clone();
+                    }
+                    sent = censored;
+
+                    yield Ok(Event::default().event("chunk").json_data(chunk).unwrap());
+                }
+                Ok(AdviceEvent::Reset) => {
+                    raw.clear();
+                    sent.clear();
+                    yield Ok(Event::default().event("reset").data(""));
+                }
+                Ok(AdviceEvent::Done(comment)) => {
+                    yield Ok(done_event(&comment));
+                    break;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::debug!("post {post_id}: advice subscriber missed {missed} events");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn done_event(comment: &Comment) -> Event {
+    let comment = Comment {
+        content: censor(&comment.content),
+        ..comment.clone()
+    };
+    Event::default().event("done").json_data(comment).unwrap()
+}
@@ -4,40 +4,84 @@ use axum::http::StatusCode;
 use axum::TypedHeader;
 
 use chrono::Utc;
-use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
-use rustrict::{Censor, Type};
+use common::AuthorKind;
 
 use sqlx::Pool;
 use sqlx::Sqlite;
 
-use crate::db::{get_last_id, store_comment, store_post};
-use server::{verify_auth, DBComment, DBPost};
+use std::sync::Arc;
+
+use crate::auth::{format_remaining, muted_for};
+use crate::db::{get_last_id, log_event, store_comment, store_post, store_risk_review};
+use crate::jobs::{JobQueue, LOADING};
+use crate::moderation::{ContentKind, Moderation};
+use crate::risk::RiskRules;
+use crate::spam::{Spam, SpamRules};
+use server::{verify_auth, DBComment, DBPost, DBRiskReview, JobKind};
 
 /// Input: `input_content: String`
 ///
-/// Output: `(StatusCode, String)`
+/// Output: `(StatusCode, String)`, or [`Spam`] when the user posts too often or repeats themselves
+///
+/// `503 Service Unavailable` when the job queue is full, unless the post is at risk,
+/// since its pinned resources should not wait for the AI.
 pub async fn route(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(db_pool): State<Pool<Sqlite>>,
+    State(jobs): State<JobQueue>,
+    State(risk): State<Arc<RiskRules>>,
+    State(moderation): State<Arc<Moderation>>,
+    State(spam): State<Arc<SpamRules>>,
     input: String,
-) -> (StatusCode, String) {
+) -> Result<(StatusCode, String), Spam> {
     let session = verify_auth(&auth, &db_pool).await;
     if session.is_err() {
-        return (StatusCode::UNAUTHORIZED, "Wrong bearer".to_string());
+        return Ok((StatusCode::UNAUTHORIZED, "Wrong bearer".to_string()));
+    }
+
+    if let Some(remaining) = muted_for(&session.as_ref().unwrap().username, &db_pool)
+        .await
+        .unwrap()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            format!("Muted for another {}", format_remaining(remaining)),
+        ));
     }
 
-    let analysis = Censor::from_str(&input).analyze();
-    if analysis.is((Type::SEXUAL & Type::MODERATE_OR_HIGHER) | Type::OFFENSIVE) {
-        tracing::info!("{:?} filter failed: {analysis:?}", session.unwrap().username);
-        return (StatusCode::FORBIDDEN, "Cannot say that".to_string());
+    let verdict = moderation.check(ContentKind::Post, &input);
+    if !verdict.is_allowed() {
+        let username = session.unwrap().username;
+        tracing::info!("{username:?} filter failed: {verdict}");
+        log_event(&verdict.rejection(&username, &input), &db_pool)
+            .await
+            .unwrap();
+        return Ok((StatusCode::FORBIDDEN, "Cannot say that".to_string()));
     }
 
     if input.trim().is_empty() {
-        return (
+        return Ok((
             StatusCode::INTERNAL_SERVER_ERROR,
             "Cannot be empty".to_string(),
-        );
+        ));
+    }
+
+    let username = &session.as_ref().unwrap().username;
+    if let Some(spam) = spam.check_post(username, &input, &db_pool).await.unwrap() {
+        tracing::info!("{username:?} spam filter failed: {spam:?}");
+        log_event(&spam.rejection(username, &input), &db_pool)
+            .await
+            .unwrap();
+        return Err(spam);
+    }
+
+    let assessment = risk.assess(&input);
+
+    if !assessment.flagged && jobs.is_full().await.unwrap_or(true) {
+        return Ok((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "AI is busy, try again later".to_string(),
+        ));
     }
 
     let username: String = session.unwrap().username;
@@ -51,76 +95,46 @@ pub async fn route(
         id: new_post_id,
         created: Utc::now().timestamp(),
         username,
-        content: input.clone(),
+        content: input,
+        author_kind: AuthorKind::User.as_str().to_string(),
     };
 
-    let res = store_post(&post, &db_pool).await;
-
-    let loading: DBComment =
-        DBComment::new(new_comment_id, new_post_id, "AI", "Loading, please wait!");
-    store_comment(&loading, &db_pool).await.unwrap();
-
-    tokio::spawn(async move {
-        let response: String = get_advice(&input);
-
-        sqlx::query("UPDATE comments SET content = $2 WHERE id = $1")
-            .bind(new_comment_id)
-            .bind(response)
-            .execute(&db_pool)
-            .await
-            .unwrap();
-
-        tracing::info!("post {}: ai done", new_post_id);
-    });
-
-    match res {
-        Ok(_) => (StatusCode::OK, "OK, reload".to_string()),
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")),
+    if let Err(err) = store_post(&post, &db_pool).await {
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")));
     }
-}
 
-fn create_message<'a>(py: Python<'a>, content: &'a str) -> &'a PyDict {
-    let message = PyDict::new(py);
-    message.set_item("role", "user").unwrap();
-    message.set_item("content", content).unwrap();
+    if assessment.flagged {
+        tracing::warn!("post {new_post_id} flagged as at risk: {assessment:?}");
 
-    message
-}
+        let mut resources: DBComment = DBComment::new_system(
+            new_comment_id + 1,
+            new_post_id,
+            AuthorKind::System,
+            &risk.resources_message,
+        );
+        resources.pinned = true;
+        store_comment(&resources, &db_pool).await.unwrap();
+
+        let review = DBRiskReview {
+            id: 0,
+            post_id: new_post_id,
+            created: post.created,
+            score: assessment.score,
+            matched: serde_json::to_string(&assessment.matched).unwrap(),
+            reviewed_by: None,
+            reviewed_at: None,
+        };
+        store_risk_review(&review, &db_pool).await.unwrap();
+    }
 
-fn get_advice(input: &str) -> String {
-    Python::with_gil(|py| {
-        let chat: &PyAny = py.import("g4f").unwrap().getattr("ChatCompletion").unwrap();
+    let loading: DBComment =
+        DBComment::new_system(new_comment_id, new_post_id, AuthorKind::Assistant, LOADING);
+    store_comment(&loading, &db_pool).await.unwrap();
 
-        let prompt: String = format!(
-            r#"Depending on this message "{input}", what advice would you give this person? Keep your advice under 4 sentences, but try to respond in depth. Only respond with the advice."#
-        );
+    // the placeholder is replaced by a worker once the job finishes
+    jobs.enqueue(new_post_id, new_comment_id, JobKind::Advice)
+        .await
+        .unwrap();
 
-        let prompt: &PyDict = create_message(py, &prompt);
-        let messages: &PyList = PyList::new(py, vec![prompt]);
-
-        let build_args: &PyDict = PyDict::new(py);
-        build_args.set_item("messages", messages).unwrap();
-
-        let mut i = 0;
-        loop {
-            i += 1;
-            if i == 5 {
-                return "Error".to_string();
-            }
-
-            match chat.call_method("create", ("gpt-3.5-turbo",), Some(build_args)) {
-                Ok(res) => {
-                    if res.contains("chatbase.co").unwrap() {
-                        tracing::error!("ai error: wrong response, retry {i}");
-                        continue;
-                    }
-                    return res.to_string()
-                },
-                Err(err) => {
-                    tracing::error!("ai error: {}, retry {i}", err.value(py));
-                    continue;
-                }
-            }
-        }
-    })
+    Ok((StatusCode::OK, "OK, reload".to_string()))
 }
@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sqlx::{Pool, Sqlite};
+
+use server::{delete_expired_sessions, SESSION_KEY_LENGTH};
+
+/// How often expired sessions are deleted. They are rejected as soon as they expire, this only keeps the table small.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Read the key that session tokens are hashed with from `path`, as hex.
+/// If there is no file yet, a new random key is written to it, readable only by its owner.
+///
+/// The key must not be kept with the database, or reading the database would be enough to check stolen tokens.
+///
+/// # Errors
+/// Errors if the file could not be read or written, or does not hold a key.
+pub fn load_key(path: &Path) -> anyhow::Result<[u8; SESSION_KEY_LENGTH]> {
+    if path.exists() {
+        let hex = fs::read_to_string(path)
+            .with_context(|| format!("could not read the session key {}", path.display()))?;
+        let hex = hex.trim();
+
+        let mut key = [0u8; SESSION_KEY_LENGTH];
+        anyhow::ensure!(
+            hex.len() == SESSION_KEY_LENGTH * 2,
+            "the session key {} must be {SESSION_KEY_LENGTH} bytes of hex",
+            path.display()
+        );
+        for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair)?, 16)
+                .with_context(|| format!("the session key {} is not hex", path.display()))?;
+        }
+
+        tracing::info!("loaded the session key from {}", path.display());
+
+        return Ok(key);
+    }
+
+    let mut key = [0u8; SESSION_KEY_LENGTH];
+    OsRng.fill_bytes(&mut key);
+    let hex: String = key.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{hex}"))
+        .with_context(|| format!("could not write a new session key to {}", path.display()))?;
+
+    tracing::warn!("no session key at {}, made a new one", path.display());
+
+    Ok(key)
+}
+
+/// Start a task that deletes expired sessions every [`SWEEP_INTERVAL`].
+pub fn spawn_sweeper(db_pool: Pool<Sqlite>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match delete_expired_sessions(&db_pool).await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!("deleted {deleted} expired sessions"),
+                Err(err) => tracing::error!("could not delete expired sessions: {err}"),
+            }
+        }
+    });
+}
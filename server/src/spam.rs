@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use common::ModerationAction;
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::auth::format_remaining;
+use server::{DBModerationEvent, SYSTEM_ACTOR};
+
+/// At most `limit` items every `window` seconds. A `limit` of 0 turns it off.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    pub limit: u32,
+    pub window: i64,
+}
+
+/// Limits on how often each user can post and comment, and on repeating themselves.
+///
+/// Every post starts an AI job, so posts are limited much more than comments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpamRules {
+    /// Seconds during which a user cannot repeat one of their own posts or comments
+    pub duplicate_window: i64,
+    /// How alike two texts have to be to count as repeated, from 0 (anything) to 1 (the same words)
+    pub similarity: f64,
+    pub posts: Rate,
+    pub comments: Rate,
+    /// Checked on top of `comments`, to stop many comments in a short time
+    pub comment_burst: Rate,
+}
+
+impl Default for SpamRules {
+    fn default() -> Self {
+        Self {
+            duplicate_window: 60 * 60,
+            similarity: 0.8,
+            posts: Rate {
+                limit: 5,
+                window: 60 * 60,
+            },
+            comments: Rate {
+                limit: 30,
+                window: 60 * 60,
+            },
+            comment_burst: Rate {
+                limit: 5,
+                window: 60,
+            },
+        }
+    }
+}
+
+/// Which of the [`SpamRules`] was broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamKind {
+    Duplicate,
+    PostRate,
+    CommentRate,
+    CommentBurst,
+}
+
+/// A post or comment rejected by the [`SpamRules`].
+///
+/// Responds with `429 Too Many Requests` and a `Retry-After` header.
+#[derive(Debug, Clone)]
+pub struct Spam {
+    pub kind: SpamKind,
+    /// Seconds until the same post or comment would be accepted
+    pub retry_after: i64,
+}
+
+impl fmt::Display for Spam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            SpamKind::Duplicate => "Already said that",
+            SpamKind::PostRate => "Too many posts",
+            SpamKind::CommentRate => "Too many comments",
+            SpamKind::CommentBurst => "Commenting too fast",
+        };
+        write!(
+            f,
+            "{what}, try again in {}",
+            format_remaining(self.retry_after)
+        )
+    }
+}
+
+impl IntoResponse for Spam {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, self.retry_after.to_string())],
+            self.to_string(),
+        )
+            .into_response()
+    }
+}
+
+impl Spam {
+    /// The audit log entry for `username`'s `content` being rejected
+    #[must_use]
+    pub fn rejection(&self, username: &str, content: &str) -> DBModerationEvent {
+        let mut event = DBModerationEvent::new(
+            SYSTEM_ACTOR,
+            ModerationAction::SpamRejected,
+            "user",
+            username,
+        );
+        event.reason = Some(format!("{:?}", self.kind));
+        event.details = Some(content.to_string());
+        event
+    }
+}
+
+impl SpamRules {
+    /// Check a new post by `username`.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn check_post(
+        &self,
+        username: &str,
+        content: &str,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Option<Spam>, sqlx::error::Error> {
+        if let Some(spam) = self
+            .duplicate("posts", None, username, content, db_pool)
+            .await?
+        {
+            return Ok(Some(spam));
+        }
+
+        self.rate("posts", username, self.posts, SpamKind::PostRate, db_pool)
+            .await
+    }
+
+    /// Check a new comment by `username` on post `post_id`.
+    ///
+    /// Only comments on the same post count as duplicates, since short replies like "thank you" are often repeated across posts.
+    ///
+    /// # Errors
+    /// See [`sqlx::error::Error`]
+    pub async fn check_comment(
+        &self,
+        post_id: u32,
+        username: &str,
+        content: &str,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Option<Spam>, sqlx::error::Error> {
+        if let Some(spam) = self
+            .duplicate("comments", Some(post_id), username, content, db_pool)
+            .await?
+        {
+            return Ok(Some(spam));
+        }
+
+        if let Some(spam) = self
+            .rate(
+                "comments",
+                username,
+                self.comment_burst,
+                SpamKind::CommentBurst,
+                db_pool,
+            )
+            .await?
+        {
+            return Ok(Some(spam));
+        }
+
+        self.rate(
+            "comments",
+            username,
+            self.comments,
+            SpamKind::CommentRate,
+            db_pool,
+        )
+        .await
+    }
+
+    /// Whether `content` is like anything `username` stored in `table` within the `duplicate_window`,
+    /// only looking at comments on `post_id` if it is given
+    async fn duplicate(
+        &self,
+        table: &str,
+        post_id: Option<u32>,
+        username: &str,
+        content: &str,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Option<Spam>, sqlx::error::Error> {
+        let now = Utc::now().timestamp();
+        let same_post =
+            post_id.map_or_else(String::new, |post_id| format!("AND post_id = {post_id}"));
+
+        let recent: Vec<(String, i64)> = sqlx::query_as(&format!(
+            "SELECT content, created FROM {table} WHERE username = $1 AND created > $2 {same_post} ORDER BY created DESC"
+        ))
+        .bind(username)
+        .bind(now - self.duplicate_window)
+        .fetch_all(db_pool)
+        .await?;
+
+        let trigrams = trigrams(content);
+
+        Ok(recent
+            .into_iter()
+            .find(|(recent, _)| similarity(&trigrams, &self::trigrams(recent)) >= self.similarity)
+            .map(|(_, created)| Spam {
+                kind: SpamKind::Duplicate,
+                retry_after: (created + self.duplicate_window - now).max(1),
+            }))
+    }
+
+    /// Whether `username` already stored `rate.limit` items in `table` within `rate.window`
+    async fn rate(
+        &self,
+        table: &str,
+        username: &str,
+        rate: Rate,
+        kind: SpamKind,
+        db_pool: &Pool<Sqlite>,
+    ) -> Result<Option<Spam>, sqlx::error::Error> {
+        if rate.limit == 0 {
+            return Ok(None);
+        }
+
+        let now = Utc::now().timestamp();
+
+        let recent: Vec<i64> = sqlx::query_scalar(&format!(
+            "SELECT created FROM {table} WHERE username = $1 AND created > $2 ORDER BY created DESC LIMIT $3"
+        ))
+        .bind(username)
+        .bind(now - rate.window)
+        .bind(rate.limit)
+        .fetch_all(db_pool)
+        .await?;
+
+        if recent.len() < rate.limit as usize {
+            return Ok(None);
+        }
+
+        // another one is allowed once the oldest of them leaves the window
+        let oldest = recent.last().copied().unwrap_or(now);
+
+        Ok(Some(Spam {
+            kind,
+            retry_after: (oldest + rate.window - now).max(1),
+        }))
+    }
+}
+
+/// Every three characters in a row of `text`, after lowercasing and keeping only letters and digits,
+/// so that changed punctuation, spacing or case does not make a repeat look new.
+/// Works for chinese too, which has no spaces between words.
+fn trigrams(text: &str) -> HashSet<String> {
+    let chars: Vec<char> = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect();
+
+    if chars.len() < 3 {
+        return HashSet::from([chars.into_iter().collect()]);
+    }
+
+    chars
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// The Jaccard similarity of two sets of trigrams, from 0 to 1
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let similarity = a.intersection(b).count() as f64 / union as f64;
+    similarity
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use sqlx::{Pool, Sqlite};
+
+use crate::advice::hub::AdviceHub;
+use crate::config::ReportRules;
+use crate::jobs::JobQueue;
+use crate::lockout::LockoutRules;
+use crate::moderation::Moderation;
+use crate::risk::RiskRules;
+use crate::spam::SpamRules;
+
+/// Everything the routes share. Routes extract only the parts they need through [`FromRef`].
+#[derive(Clone)]
+pub struct AppState {
+    pub db_pool: Pool<Sqlite>,
+    pub jobs: JobQueue,
+    pub hub: AdviceHub,
+    pub risk: Arc<RiskRules>,
+    pub moderation: Arc<Moderation>,
+    pub reports: Arc<ReportRules>,
+    pub spam: Arc<SpamRules>,
+    pub lockout: Arc<LockoutRules>,
+}
+
+impl FromRef<AppState> for Pool<Sqlite> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db_pool.clone()
+    }
+}
+
+impl FromRef<AppState> for JobQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for AdviceHub {
+    fn from_ref(state: &AppState) -> Self {
+        state.hub.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<RiskRules> {
+    fn from_ref(state: &AppState) -> Self {
+        state.risk.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Moderation> {
+    fn from_ref(state: &AppState) -> Self {
+        state.moderation.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ReportRules> {
+    fn from_ref(state: &AppState) -> Self {
+        state.reports.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<SpamRules> {
+    fn from_ref(state: &AppState) -> Self {
+        state.spam.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LockoutRules> {
+    fn from_ref(state: &AppState) -> Self {
+        state.lockout.clone()
+    }
+}